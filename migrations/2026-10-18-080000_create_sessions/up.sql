@@ -0,0 +1,9 @@
+CREATE TABLE sessions (
+       id SERIAL PRIMARY KEY,
+       "user" INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+       refresh_hash TEXT NOT NULL,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+       expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+       revoked_at TIMESTAMP WITH TIME ZONE
+);
+CREATE INDEX sessions_user_idx ON sessions ("user");
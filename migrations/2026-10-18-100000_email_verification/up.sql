@@ -0,0 +1,12 @@
+ALTER TABLE users ADD COLUMN verified_at TIMESTAMP WITH TIME ZONE;
+-- accounts created before verification existed are trusted
+UPDATE users SET verified_at = NOW();
+
+CREATE TABLE outbox (
+       id SERIAL PRIMARY KEY,
+       recipient TEXT NOT NULL,
+       subject TEXT NOT NULL,
+       body TEXT NOT NULL,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+       sent_at TIMESTAMP WITH TIME ZONE
+);
@@ -0,0 +1,10 @@
+CREATE TABLE password_resets (
+       id SERIAL PRIMARY KEY,
+       "user" INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+       token_hash TEXT NOT NULL,
+       attempts INTEGER NOT NULL DEFAULT 0,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+       expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+       used_at TIMESTAMP WITH TIME ZONE
+);
+CREATE INDEX password_resets_user_idx ON password_resets ("user");
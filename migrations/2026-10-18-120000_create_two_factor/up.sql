@@ -0,0 +1,16 @@
+CREATE TABLE totp_credentials (
+       "user" INTEGER PRIMARY KEY REFERENCES users ON DELETE CASCADE,
+       secret TEXT NOT NULL,
+       enabled_at TIMESTAMP WITH TIME ZONE,
+       -- time step of the last accepted code, a code works only once
+       last_step BIGINT,
+       failures INTEGER NOT NULL DEFAULT 0,
+       failed_at TIMESTAMP WITH TIME ZONE
+);
+CREATE TABLE recovery_codes (
+       id SERIAL PRIMARY KEY,
+       "user" INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+       code_hash TEXT NOT NULL,
+       used_at TIMESTAMP WITH TIME ZONE
+);
+CREATE INDEX recovery_codes_user_idx ON recovery_codes ("user");
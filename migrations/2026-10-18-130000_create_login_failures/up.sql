@@ -0,0 +1,7 @@
+-- failed logins per account ('email:...') and per client address ('ip:...')
+CREATE TABLE login_failures (
+       key TEXT PRIMARY KEY,
+       failures INTEGER NOT NULL DEFAULT 0,
+       last_failed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+       locked_until TIMESTAMP WITH TIME ZONE
+);
@@ -0,0 +1,10 @@
+-- accounts of an OpenID Connect provider, `subject` is the provider's `sub`
+CREATE TABLE user_identities (
+       id SERIAL PRIMARY KEY,
+       "user" INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+       issuer TEXT NOT NULL,
+       subject TEXT NOT NULL,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+       UNIQUE (issuer, subject)
+);
+CREATE INDEX user_identities_user_idx ON user_identities ("user");
@@ -0,0 +1,13 @@
+-- personal access tokens, for bots and scripts
+CREATE TABLE access_tokens (
+       id SERIAL PRIMARY KEY,
+       "user" INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+       name TEXT NOT NULL,
+       token_hash TEXT NOT NULL,
+       scopes TEXT[] NOT NULL,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+       expires_at TIMESTAMP WITH TIME ZONE,
+       last_used_at TIMESTAMP WITH TIME ZONE,
+       revoked_at TIMESTAMP WITH TIME ZONE
+);
+CREATE INDEX access_tokens_user_idx ON access_tokens ("user");
@@ -0,0 +1,24 @@
+-- who did what and when; actor has no foreign key, the trail outlives the user
+CREATE TABLE audit_events (
+       id SERIAL PRIMARY KEY,
+       actor INTEGER,
+       actor_name TEXT,
+       action TEXT NOT NULL,
+       target TEXT,
+       ip TEXT,
+       diff JSONB NOT NULL DEFAULT '{}',
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE INDEX audit_events_actor_idx ON audit_events (actor, created_at);
+CREATE INDEX audit_events_action_idx ON audit_events (action, created_at);
+
+-- append-only: events can't be changed or removed, not even by the app
+CREATE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+BEGIN
+       RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+       BEFORE UPDATE OR DELETE ON audit_events
+       FOR EACH ROW EXECUTE PROCEDURE audit_events_append_only();
@@ -0,0 +1,11 @@
+DROP TRIGGER users_search_update ON users;
+DROP TRIGGER comments_search_update ON comments;
+DROP TRIGGER articles_search_update ON articles;
+DROP FUNCTION users_search_update();
+DROP FUNCTION comments_search_update();
+DROP FUNCTION articles_search_update();
+ALTER TABLE users DROP COLUMN search;
+ALTER TABLE comments DROP COLUMN search;
+ALTER TABLE articles DROP COLUMN search;
+DROP FUNCTION search_language();
+DROP TABLE search_settings;
@@ -0,0 +1,61 @@
+-- the text search configuration of all documents and queries, set at startup from SEARCH_LANGUAGE
+CREATE TABLE search_settings (
+       id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+       language REGCONFIG NOT NULL DEFAULT 'english'
+);
+INSERT INTO search_settings DEFAULT VALUES;
+
+CREATE FUNCTION search_language() RETURNS REGCONFIG AS $$
+       SELECT language FROM search_settings
+$$ LANGUAGE SQL STABLE;
+
+ALTER TABLE articles ADD COLUMN search TSVECTOR;
+ALTER TABLE comments ADD COLUMN search TSVECTOR;
+ALTER TABLE users ADD COLUMN search TSVECTOR;
+
+-- the triggers also run when `search` itself is set, `SET search = NULL` rebuilds it
+CREATE FUNCTION articles_search_update() RETURNS trigger AS $$
+BEGIN
+       NEW.search :=
+              setweight(to_tsvector(search_language(), NEW.title), 'A') ||
+              setweight(to_tsvector(search_language(), NEW.description), 'B') ||
+              setweight(to_tsvector(search_language(), NEW.body), 'C');
+       RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER articles_search_update
+       BEFORE INSERT OR UPDATE OF title, description, body, search ON articles
+       FOR EACH ROW EXECUTE PROCEDURE articles_search_update();
+
+CREATE FUNCTION comments_search_update() RETURNS trigger AS $$
+BEGIN
+       NEW.search := to_tsvector(search_language(), NEW.body);
+       RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER comments_search_update
+       BEFORE INSERT OR UPDATE OF body, search ON comments
+       FOR EACH ROW EXECUTE PROCEDURE comments_search_update();
+
+CREATE FUNCTION users_search_update() RETURNS trigger AS $$
+BEGIN
+       NEW.search :=
+              setweight(to_tsvector('simple', NEW.username), 'A') ||
+              setweight(to_tsvector(search_language(), coalesce(NEW.bio, '')), 'B');
+       RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER users_search_update
+       BEFORE INSERT OR UPDATE OF username, bio, search ON users
+       FOR EACH ROW EXECUTE PROCEDURE users_search_update();
+
+UPDATE articles SET search = NULL;
+UPDATE comments SET search = NULL;
+UPDATE users SET search = NULL;
+
+CREATE INDEX articles_search_idx ON articles USING GIN (search);
+CREATE INDEX comments_search_idx ON comments USING GIN (search);
+CREATE INDEX users_search_idx ON users USING GIN (search);
@@ -0,0 +1,7 @@
+-- drafts are only seen by their author, scheduled articles are published at published_at
+ALTER TABLE articles ADD COLUMN status TEXT NOT NULL DEFAULT 'published'
+       CHECK (status IN ('draft', 'published', 'scheduled'));
+ALTER TABLE articles ADD COLUMN published_at TIMESTAMP WITH TIME ZONE;
+UPDATE articles SET published_at = created_at;
+CREATE INDEX articles_published_at_idx ON articles (published_at);
+CREATE INDEX articles_scheduled_idx ON articles (published_at) WHERE status = 'scheduled';
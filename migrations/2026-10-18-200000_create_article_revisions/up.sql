@@ -0,0 +1,16 @@
+-- the full content of an article after every change, numbered from 1 per article
+CREATE TABLE article_revisions (
+       id SERIAL PRIMARY KEY,
+       article INTEGER NOT NULL REFERENCES articles ON DELETE CASCADE,
+       number INTEGER NOT NULL,
+       editor INTEGER REFERENCES users ON DELETE SET NULL,
+       title TEXT NOT NULL,
+       description TEXT NOT NULL,
+       body TEXT NOT NULL,
+       tag_list TEXT[] NOT NULL,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+       UNIQUE (article, number)
+);
+-- the articles written so far start with what they are now
+INSERT INTO article_revisions (article, number, editor, title, description, body, tag_list, created_at)
+       SELECT id, 1, author, title, description, body, tag_list, updated_at FROM articles;
@@ -0,0 +1,3 @@
+-- the body rendered from Markdown, written with the body; NULL ones are filled at startup
+ALTER TABLE articles ADD COLUMN body_html TEXT;
+ALTER TABLE comments ADD COLUMN body_html TEXT;
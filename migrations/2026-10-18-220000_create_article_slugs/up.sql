@@ -0,0 +1,7 @@
+-- slugs an article had before its title changed, old links still find it
+CREATE TABLE article_slugs (
+       slug TEXT PRIMARY KEY,
+       article INTEGER NOT NULL REFERENCES articles ON DELETE CASCADE,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE INDEX article_slugs_article_idx ON article_slugs (article);
@@ -0,0 +1,29 @@
+-- the tags of articles, kept in step with articles.tag_list
+CREATE TABLE tags (
+       id SERIAL PRIMARY KEY,
+       name TEXT NOT NULL UNIQUE,
+       -- refused on articles and removed from them
+       blocked BOOLEAN NOT NULL DEFAULT FALSE,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE TABLE article_tags (
+       article INTEGER NOT NULL REFERENCES articles ON DELETE CASCADE,
+       tag INTEGER NOT NULL REFERENCES tags ON DELETE CASCADE,
+       PRIMARY KEY (article, tag)
+);
+CREATE INDEX article_tags_tag_idx ON article_tags (tag);
+
+-- the tags written so far in lower case with single spaces, without duplicates
+UPDATE articles SET tag_list = ARRAY(
+       SELECT name FROM (
+              SELECT lower(regexp_replace(btrim(t), '\s+', ' ', 'g')) AS name, min(n) AS n
+              FROM unnest(tag_list) WITH ORDINALITY AS u(t, n)
+              WHERE btrim(t) <> ''
+              GROUP BY 1
+       ) normalized ORDER BY n
+);
+INSERT INTO tags (name) SELECT DISTINCT unnest(tag_list) FROM articles;
+INSERT INTO article_tags (article, tag)
+       SELECT articles.id, tags.id
+       FROM articles CROSS JOIN LATERAL unnest(articles.tag_list) AS u(name)
+       JOIN tags ON tags.name = u.name;
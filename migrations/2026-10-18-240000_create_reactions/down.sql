@@ -0,0 +1,2 @@
+DROP TABLE comment_reactions;
+DROP TABLE article_reactions;
@@ -0,0 +1,15 @@
+-- emoji reactions of users, at most one of each emoji per user on an article or comment
+CREATE TABLE article_reactions (
+       article INTEGER NOT NULL REFERENCES articles ON DELETE CASCADE,
+       "user" INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+       emoji TEXT NOT NULL,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+       PRIMARY KEY (article, "user", emoji)
+);
+CREATE TABLE comment_reactions (
+       comment INTEGER NOT NULL REFERENCES comments ON DELETE CASCADE,
+       "user" INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+       emoji TEXT NOT NULL,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+       PRIMARY KEY (comment, "user", emoji)
+);
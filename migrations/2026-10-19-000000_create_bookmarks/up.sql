@@ -0,0 +1,10 @@
+-- private bookmarks of articles, in an optional folder with a note
+CREATE TABLE bookmarks (
+       "user" INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+       article INTEGER NOT NULL REFERENCES articles ON DELETE CASCADE,
+       folder TEXT,
+       note TEXT,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+       PRIMARY KEY ("user", article)
+);
+CREATE INDEX bookmarks_user_created_at_idx ON bookmarks ("user", created_at DESC, article DESC);
@@ -0,0 +1,3 @@
+ALTER TABLE favorites DROP COLUMN created_at;
+ALTER TABLE articles DROP COLUMN views_count;
+DROP TABLE article_views;
@@ -0,0 +1,12 @@
+-- views of an article per day, for the analytics of its author
+CREATE TABLE article_views (
+       article INTEGER NOT NULL REFERENCES articles ON DELETE CASCADE,
+       day DATE NOT NULL,
+       views INTEGER NOT NULL DEFAULT 0,
+       PRIMARY KEY (article, day)
+);
+ALTER TABLE articles ADD COLUMN views_count INTEGER NOT NULL DEFAULT 0;
+
+-- favorites from before have no time, they only count without a date range
+ALTER TABLE favorites ADD COLUMN created_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE favorites ALTER COLUMN created_at SET DEFAULT NOW();
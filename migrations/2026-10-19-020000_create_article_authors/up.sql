@@ -0,0 +1,9 @@
+-- co-authors of an article, invited by its author until they accept
+CREATE TABLE article_authors (
+       article INTEGER NOT NULL REFERENCES articles ON DELETE CASCADE,
+       "user" INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+       accepted_at TIMESTAMP WITH TIME ZONE,
+       PRIMARY KEY (article, "user")
+);
+CREATE INDEX article_authors_user_idx ON article_authors ("user");
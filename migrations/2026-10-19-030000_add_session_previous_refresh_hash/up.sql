@@ -0,0 +1,2 @@
+-- the refresh token rotated out last, presenting it again means it was copied
+ALTER TABLE sessions ADD COLUMN previous_refresh_hash TEXT;
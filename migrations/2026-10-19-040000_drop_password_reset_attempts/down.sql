@@ -0,0 +1 @@
+ALTER TABLE password_resets ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
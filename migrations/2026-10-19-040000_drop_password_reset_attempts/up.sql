@@ -0,0 +1,2 @@
+-- wrong guesses lock the resets of the account in login_failures instead
+ALTER TABLE password_resets DROP COLUMN attempts;
@@ -0,0 +1,2 @@
+-- writes with the session cookie repeat it in the X-CSRF-Token header
+ALTER TABLE sessions ADD COLUMN csrf_token TEXT;
@@ -1,7 +1,9 @@
-use crate::db;
-use frank_jwt::{decode, encode, Algorithm, ValidationOptions};
+use crate::keys::{self, KEYRING};
+use crate::policy::{Role, Scope};
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::json; //not use json! in rocket_contrib
+use serde_json::Value;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Auth {
@@ -10,41 +12,84 @@ pub struct Auth {
     /// user id
     pub id: i32,
     pub username: String,
+    /// login session id, revoking the session invalidates the token
+    #[serde(default)]
+    pub sid: i32,
+    #[serde(default)]
+    pub role: Role,
+    /// set when the request came with a personal access token, which is only
+    /// good for routes taking one of its scopes
+    #[serde(skip)]
+    pub scopes: Option<Vec<Scope>>,
 }
 impl Auth {
     pub fn token(&self) -> String {
-        let headers = json!({});
-        let payload = json!(self);
-        let jwt = encode(
-            headers,
-            &db::CONFIG.secret_key,
-            // &config::SECRET.to_string(),
-            &payload,
-            Algorithm::HS256,
-        );
-        jwt.unwrap()
+        sign(&json!(self))
     }
+
+    /// Logins may do anything, personal access tokens only what their scopes allow.
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.contains(&scope))
+    }
+}
+
+/// Sign a JWT with the current key of the keyring, its `kid` goes in the header.
+pub fn sign(payload: &Value) -> String {
+    let key = KEYRING.signing().expect("No JWT signing key");
+    let headers = json!({ "kid": key.kid });
+    keys::encode(headers, key.signing_key().unwrap(), payload, key.alg).unwrap()
+}
+
+/// Verify a JWT against the key named in its header, return the payload.
+pub fn verify(token: &str) -> Option<Value> {
+    let header = keys::token_header(token)?;
+    let key = KEYRING.verifying(header["kid"].as_str())?;
+    keys::decode(token, key.verifying_key(), key.alg)
+        .map_err(|err| {
+            eprintln!("Auth decode error: {}", err);
+        })
+        .ok()
+}
+
+/// Sign a single purpose token, e.g. for the link of a verification mail.
+/// `subject` ties it to the state it was issued for, like the email address.
+pub fn purpose_token(purpose: &str, id: i32, subject: &str, minutes: i64) -> String {
+    let exp = Utc::now() + Duration::minutes(minutes);
+    sign(&json!({
+        "purpose": purpose,
+        "id": id,
+        "sub": subject,
+        "exp": exp.timestamp(),
+    }))
+}
+
+/// Check a token made by `purpose_token`, return the user id and subject.
+pub fn decode_purpose_token(token: &str, purpose: &str) -> Option<(i32, String)> {
+    let payload = verify(token)?;
+    if payload["purpose"].as_str() != Some(purpose)
+        || payload["exp"].as_i64()? < Utc::now().timestamp()
+    {
+        return None;
+    }
+    let id = payload["id"].as_i64()? as i32;
+    let subject = payload["sub"].as_str()?.to_string();
+    Some((id, subject))
 }
 
 /// Decode token into `Auth` struct. If any error is encountered, log it
 /// an return None.
 pub fn decode_token(token: &str) -> Option<Auth> {
-    decode(
-        token,
-        // &config::SECRET.to_string(),
-        &db::CONFIG.secret_key,
-        Algorithm::HS256,
-        &ValidationOptions::default(),
-    )
-    .map(|(_, payload)| {
+    verify(token).and_then(|payload| {
+        // a single purpose token is no login
+        if payload.get("purpose").is_some() {
+            return None;
+        }
         serde_json::from_value::<Auth>(payload)
             .map_err(|err| {
                 eprintln!("Auth serde decode error: {:?}", err);
             })
             .ok()
     })
-    .unwrap_or_else(|err| {
-        eprintln!("Auth decode error: {:?}", err);
-        None
-    })
 }
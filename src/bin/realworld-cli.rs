@@ -5,15 +5,18 @@ use diesel;
 use diesel::pg::PgConnection;
 use diesel::{prelude::*, sql_query};
 // use serde::Serialize;
+use serde_json::json;
 use structopt::StructOpt;
 
 use realworld_warp::db;
+use realworld_warp::db::audit::{self, Actor, FindEvents};
+use realworld_warp::db::search::Search;
 use realworld_warp::models::user::User;
-use realworld_warp::schema::articles;
+use realworld_warp::policy::Role;
 use realworld_warp::schema::users;
 //use realworld_warp::models::user::Follow;
 use realworld_warp::db::articles::{find, FindArticles};
-use realworld_warp::db::OffsetLimit;
+use realworld_warp::db::{OffsetLimit, Paging};
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -35,6 +38,12 @@ struct Opt {
     title: Option<String>,
     #[structopt(short, long)]
     id: Option<i32>,
+    #[structopt(short, long)]
+    role: Option<String>,
+    #[structopt(long)]
+    action: Option<String>,
+    #[structopt(long)]
+    target: Option<String>,
 }
 // in structopt, bool option is very interesting, only bool means FLAG giving is working, like "--all"
 // if Option<bool>, then must "--all true|flase"
@@ -47,24 +56,23 @@ fn article_find(opt: &Opt, conn: &PgConnection) {
             favorited: None,
             limit: Some(100),
             offset: Some(0),
+            after: None,
+            before: None,
         };
-        let (res, _) = find(&conn, &fa, Some(0));
-        for a in res {
+        let page = find(conn, &fa, &Paging::Offset(0), Some(0));
+        for a in page.items {
             println!("\tid: {}\t title: {}", a.id, a.title);
         }
     } else {
-        articles::table
-            .select((articles::id, articles::title))
-            .into_boxed()
-            .filter(articles::title.like(format!("%{}%", opt.title.clone().unwrap())))
-            .load::<(i32, String)>(conn)
-            .map(|res| {
-                for r in res {
-                    println!("\tid: {}\t title: {}", r.0, r.1);
-                }
-            })
-            .ok()
-            .unwrap();
+        let params = Search {
+            limit: Some(100),
+            ..Search::default()
+        };
+        let title = opt.title.as_deref().unwrap_or_default();
+        let (res, _) = db::search::articles(conn, title, &params, None);
+        for hit in res {
+            println!("\tid: {}\t title: {}", hit.item.id, hit.item.title);
+        }
     }
 }
 fn article_opt(opt: &Opt) {
@@ -153,18 +161,79 @@ fn get_users(opt: &Opt, conn: &PgConnection) -> Option<Vec<User>> {
 
 fn user_delete(opt: &Opt, conn: &PgConnection) {
     if let Some(uid) = &opt.id {
-        diesel::delete(users::table.filter(users::id.eq(uid)))
-            .execute(conn)
+        let deleted = diesel::delete(users::table.filter(users::id.eq(uid)))
+            .get_result::<User>(conn)
             .ok();
+        if let Some(u) = deleted {
+            audit::record(
+                conn,
+                &Actor::cli(),
+                audit::USER_DELETE,
+                Some(&format!("user:{}", u.id)),
+                audit::diff(
+                    &json!({ "username": u.username, "email": u.email }),
+                    &json!({}),
+                ),
+            );
+        }
     } else {
         println!("Error: delete function must have <id> provide!");
         return;
     }
 }
+fn user_role(opt: &Opt, conn: &PgConnection) {
+    let role = match opt.role.as_ref().map(|r| r.parse::<Role>()) {
+        Some(Ok(role)) => role,
+        _ => {
+            println!("Error: role function must have <role> [user | moderator | admin] provide!");
+            return;
+        }
+    };
+    if let Some(uid) = &opt.id {
+        let old_role = db::users::find(conn, *uid).map(|u| u.role);
+        match db::users::set_role(conn, *uid, role) {
+            Some(u) => {
+                audit::record(
+                    conn,
+                    &Actor::cli(),
+                    audit::USER_ROLE,
+                    Some(&format!("user:{}", u.id)),
+                    audit::diff(&json!({ "role": old_role }), &json!({ "role": u.role })),
+                );
+                println!("id: {}\t name: {}\t role: {}", u.id, u.username, u.role)
+            }
+            None => println!("Error: user {} not found!", uid),
+        }
+    } else {
+        println!("Error: role function must have <id> provide!");
+    }
+}
+fn user_unlock(opt: &Opt, conn: &PgConnection) {
+    if let Some(mail) = &opt.email {
+        let key = db::login_failures::account_key(mail);
+        if db::login_failures::clear(conn, &key) {
+            audit::record(
+                conn,
+                &Actor::cli(),
+                audit::USER_UNLOCK,
+                Some(&format!("email:{}", mail)),
+                json!({}),
+            );
+            println!("{} unlocked", mail);
+        } else {
+            println!("{} has no failed logins", mail);
+        }
+    } else {
+        println!("Error: unlock function must have <email> provide!");
+    }
+}
 fn user_find(opt: &Opt, conn: &PgConnection) {
     get_users(&opt, &conn).map(|res| {
         for u in &res {
-            println!("id: {}\t name: {}\t email: {}", u.id, u.username, u.email);
+            println!(
+                "id: {}\t name: {}\t email: {}\t role: {}",
+                u.id, u.username, u.email, u.role
+            );
         }
     });
     // println!("{}",u.len());
@@ -175,6 +244,39 @@ fn user_opt(opt: &Opt) {
     match opt.function.as_ref() {
         "ls" => user_find(&opt, &conn),
         "delete" => user_delete(&opt, &conn),
+        "role" => user_role(opt, &conn),
+        "unlock" => user_unlock(opt, &conn),
+        _ => println!("Error: no this function!"),
+    }
+}
+
+//----------------------------------------------------------------------
+fn audit_find(opt: &Opt, conn: &PgConnection) {
+    let params = FindEvents {
+        actor: opt.id,
+        action: opt.action.clone(),
+        target: opt.target.clone(),
+        limit: Some(100),
+        ..FindEvents::default()
+    };
+    let (events, count) = audit::find(conn, &params);
+    for e in events {
+        println!(
+            "{}\t {}\t actor: {}\t target: {}\t ip: {}\t {}",
+            e.created_at.format("%Y-%m-%d %H:%M:%S"),
+            e.action,
+            e.actor_name.unwrap_or_default(),
+            e.target.unwrap_or_default(),
+            e.ip.unwrap_or_default(),
+            e.diff
+        );
+    }
+    println!("{} event(s)", count);
+}
+fn audit_opt(opt: &Opt) {
+    let conn = db::PG_POOL.clone().get().unwrap();
+    match opt.function.as_ref() {
+        "ls" => audit_find(opt, &conn),
         _ => println!("Error: no this function!"),
     }
 }
@@ -188,6 +290,7 @@ fn main() {
         "user" => user_opt(&opt),
         "article" => article_opt(&opt),
         "follow" => follow_opt(&opt),
+        "audit" => audit_opt(&opt),
         _ => println!("Error: no this model!"),
     }
 }
@@ -3,7 +3,59 @@ pub const DATE_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S%.3fZ";
 
 pub const SECRET: &'static str = "secret123";
 pub const TOKEN_PREFIX: &'static str = "Token ";
+/// cookies of `COOKIE_SESSIONS`, the CSRF token of the session is repeated in `CSRF_HEADER` on writes
+pub const SESSION_COOKIE: &str = "session";
+pub const REFRESH_COOKIE: &str = "refresh_token";
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+/// access tokens are short-lived, clients renew them with the refresh token
+pub const ACCESS_EXPIRE_MINUTES: i64 = 15;
+/// lifetime of a login session (the refresh token)
 pub const LOGIN_EXPIRE_DAYS: i64 = 60;
+/// how often the last seen time of a session is written
+pub const SESSION_SEEN_MINUTES: i64 = 5;
+/// lifetime of the link in the verification mail
+pub const VERIFY_EXPIRE_MINUTES: i64 = 48 * 60;
+/// lifetime of a password reset token
+pub const RESET_EXPIRE_MINUTES: i64 = 60;
+/// reset mails sent per account and hour
+pub const RESET_REQUESTS_PER_HOUR: i64 = 3;
+/// wrong reset tokens for an account before its resets lock like a login
+pub const RESET_MAX_ATTEMPTS: i32 = 5;
+/// time to enter the second factor after the password
+pub const CHALLENGE_EXPIRE_MINUTES: i64 = 5;
+/// wrong codes before the second factor locks for `TOTP_LOCK_MINUTES`
+pub const TOTP_MAX_FAILURES: i32 = 5;
+pub const TOTP_LOCK_MINUTES: i64 = 5;
+pub const RECOVERY_CODES: usize = 10;
+/// first lockout after too many failed logins, doubled with every further failure
+pub const LOGIN_LOCK_SECONDS: i64 = 30;
+pub const LOGIN_LOCK_MAX_SECONDS: i64 = 60 * 60;
+/// failed logins are forgotten after a quiet day
+pub const LOGIN_FAILURES_RESET_HOURS: i64 = 24;
+/// time to come back from the OpenID Connect provider
+pub const OIDC_LOGIN_EXPIRE_MINUTES: i64 = 10;
+/// ties the login to the browser that started it
+pub const OIDC_STATE_COOKIE: &str = "oidc_state";
+/// tags of an article, and characters of a tag
+pub const MAX_TAGS: usize = 10;
+pub const MAX_TAG_LENGTH: usize = 32;
+/// characters of a bookmark folder and note
+pub const MAX_FOLDER_LENGTH: usize = 64;
+pub const MAX_NOTE_LENGTH: usize = 2000;
+/// reactions offered when `REACTIONS` isn't set
+pub const DEFAULT_REACTIONS: &str = "👍,🎉,❤️,🤔";
+/// a reader counts once per article in this time
+pub const VIEW_WINDOW_MINUTES: i64 = 30;
+/// readers remembered for `VIEW_WINDOW_MINUTES`, the oldest are forgotten beyond
+pub const VIEW_READERS_MAX: usize = 100_000;
+/// how often counted views are written, they are lost on a crash since
+pub const VIEWS_FLUSH_SECONDS: u64 = 60;
+/// days of `/api/user/analytics` by default, and at most
+pub const ANALYTICS_DAYS: i64 = 30;
+pub const ANALYTICS_MAX_DAYS: i64 = 366;
+/// how often scheduled articles are checked, they are published up to this late
+pub const PUBLISH_CHECK_SECONDS: u64 = 30;
 
 pub const STATIC_PATH: &'static str = "./dist/";
 pub const DOWNLOAD_PATH: &'static str = "./dl/";
@@ -0,0 +1,101 @@
+use crate::auth::Auth;
+use crate::db::{generate_secret, hash_secret, parse_token, secrets_equal};
+use crate::models::access_token::AccessToken;
+use crate::models::user::User;
+use crate::policy::Scope;
+use crate::schema::{access_tokens, users};
+use chrono::{DateTime, Utc};
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+/// Personal access tokens look like `rwp_<id>.<secret>`, so they are told
+/// apart from JWTs (and found by secret scanners).
+pub const TOKEN_PREFIX: &str = "rwp_";
+
+/// Create a token, return it with the plain token shown to the user once.
+pub fn create(
+    conn: &PgConnection,
+    user_id: i32,
+    name: &str,
+    scopes: &[Scope],
+    expires_at: Option<DateTime<Utc>>,
+) -> (AccessToken, String) {
+    let secret = generate_secret();
+    let scopes: Vec<&str> = scopes.iter().map(Scope::as_str).collect();
+    let access_token = diesel::insert_into(access_tokens::table)
+        .values((
+            access_tokens::user.eq(user_id),
+            access_tokens::name.eq(name),
+            access_tokens::token_hash.eq(hash_secret(&secret)),
+            access_tokens::scopes.eq(scopes),
+            access_tokens::expires_at.eq(expires_at),
+        ))
+        .get_result::<AccessToken>(conn)
+        .expect("Error creating access token");
+    let token = format!("{}{}.{}", TOKEN_PREFIX, access_token.id, secret);
+    (access_token, token)
+}
+
+/// The tokens of a user that were not revoked, the newest first.
+pub fn list(conn: &PgConnection, user_id: i32) -> Vec<AccessToken> {
+    access_tokens::table
+        .filter(access_tokens::user.eq(user_id))
+        .filter(access_tokens::revoked_at.is_null())
+        .order(access_tokens::id.desc())
+        .load(conn)
+        .expect("Cannot load access tokens")
+}
+
+/// Revoke a token of the user, false when there is no such token.
+pub fn revoke(conn: &PgConnection, id: i32, user_id: i32) -> bool {
+    diesel::update(
+        access_tokens::table
+            .find(id)
+            .filter(access_tokens::user.eq(user_id))
+            .filter(access_tokens::revoked_at.is_null()),
+    )
+    .set(access_tokens::revoked_at.eq(Utc::now()))
+    .execute(conn)
+    .map(|count| count > 0)
+    .expect("Error revoking access token")
+}
+
+/// The `Auth` of a request made with a personal access token, None when the
+/// token is unknown, revoked or expired. Records when it was last used.
+pub fn authenticate(conn: &PgConnection, token: &str) -> Option<Auth> {
+    let (id, secret) = parse_token(token.strip_prefix(TOKEN_PREFIX)?)?;
+    let now = Utc::now();
+    let (access_token, user) = access_tokens::table
+        .inner_join(users::table)
+        .filter(access_tokens::id.eq(id))
+        .filter(access_tokens::revoked_at.is_null())
+        .get_result::<(AccessToken, User)>(conn)
+        .optional()
+        .expect("Error loading access token")?;
+    if !secrets_equal(&access_token.token_hash, &hash_secret(secret))
+        || access_token.expires_at.is_some_and(|t| t <= now)
+    {
+        return None;
+    }
+
+    diesel::update(access_tokens::table.find(id))
+        .set(access_tokens::last_used_at.eq(now))
+        .execute(conn)
+        .map_err(|err| eprintln!("access_tokens::authenticate: {}", err))
+        .ok();
+    Some(Auth {
+        exp: access_token.expires_at.map_or(i64::MAX, |t| t.timestamp()),
+        id: user.id,
+        username: user.username.clone(),
+        sid: 0,
+        role: user.role(),
+        scopes: Some(
+            access_token
+                .scopes
+                .iter()
+                .filter_map(|s| s.parse().ok())
+                .collect(),
+        ),
+    })
+}
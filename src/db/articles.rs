@@ -1,14 +1,26 @@
+use crate::auth::Auth;
+use crate::db::authors;
+use crate::db::bookmarks;
 use crate::db::profiles::is_following;
-use crate::db::OffsetLimit;
-use crate::models::article::{Article, ArticleJson, ArticleProfileJson};
+use crate::db::reactions;
+use crate::db::revisions;
+use crate::db::tags;
+use crate::db::{Cursor, OffsetLimit, Page, Paging, WriteError, CONFIG};
+use crate::markdown;
+use crate::models::article::{Article, ArticleJson, ArticleProfileJson, Status};
 use crate::models::user::User;
+use crate::policy;
+use crate::schema::article_authors;
+use crate::schema::article_slugs;
 use crate::schema::articles;
 use crate::schema::favorites;
 use crate::schema::follows;
 use crate::schema::users;
+use chrono::{DateTime, Utc};
 use diesel;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::Deserialize;
 use slug;
@@ -25,8 +37,12 @@ struct NewArticle<'a> {
     slug: &'a str,
     author: i32,
     tag_list: &'a Vec<String>,
+    status: &'a str,
+    published_at: Option<DateTime<Utc>>,
+    body_html: &'a str,
 }
 
+/// `status` is a draft or published, scheduling goes through `publish`.
 pub fn create(
     conn: &PgConnection,
     author: i32,
@@ -34,6 +50,7 @@ pub fn create(
     description: &str,
     body: &str,
     tag_list: &Vec<String>,
+    status: Status,
 ) -> ArticleJson {
     let new_article = &NewArticle {
         title,
@@ -42,6 +59,9 @@ pub fn create(
         author,
         tag_list,
         slug: &slugify(title),
+        status: status.as_str(),
+        published_at: Some(Utc::now()).filter(|_| status == Status::Published),
+        body_html: &markdown::render(body),
     };
 
     let author = users::table
@@ -49,11 +69,23 @@ pub fn create(
         .get_result::<User>(conn)
         .expect("Error loading author");
 
-    diesel::insert_into(articles::table)
-        .values(new_article)
-        .get_result::<Article>(conn)
-        .expect("Error creating article")
-        .attach(author, false)
+    let article = conn
+        .transaction::<_, diesel::result::Error, _>(|| {
+            let article = diesel::insert_into(articles::table)
+                .values(new_article)
+                .get_result::<Article>(conn)?;
+            tags::sync(conn, &article)?;
+            revisions::record(conn, &article, article.author)?;
+            Ok(article)
+        })
+        .expect("Error creating article");
+
+    let mut json = article.attach(author, false);
+    json.authors = authors::of_articles(conn, &[json.id], Some(json.author.id))
+        .remove(&json.id)
+        .unwrap_or_default();
+    json.bookmarked = Some(false);
+    json
 }
 
 fn slugify(title: &str) -> String {
@@ -69,6 +101,45 @@ fn generate_suffix(len: usize) -> String {
     (0..len).map(|_| rng.sample(Alphanumeric)).collect()
 }
 
+/// Order a boxed `(Article, User, favorited)` query the newest first, the
+/// drafts of the user before them, and load the page of it.
+macro_rules! load_page {
+    ($query:expr, $paging:expr, $limit:expr, $conn:expr) => {{
+        let paging: &Paging = $paging;
+        let limit: i64 = $limit;
+        let mut query = $query;
+        if let Some(keyset) = paging.keyset("articles.published_at", "articles.id") {
+            query = query.filter(keyset);
+        }
+        query = if paging.is_reversed() {
+            query.order((articles::published_at.asc(), articles::id.asc()))
+        } else {
+            query.order((articles::published_at.desc(), articles::id.desc()))
+        };
+        let key = |(article, _, _): &(Article, User, bool)| Cursor {
+            at: article.published_at,
+            id: article.id,
+        };
+        match paging {
+            Paging::Offset(offset) => {
+                let (rows, count) = query
+                    .offset_and_limit(*offset, limit)
+                    .load_and_count::<(Article, User, bool)>($conn)
+                    .expect("Cannot load articles");
+                paging.page(limit, rows, Some(count), key)
+            }
+            _ => {
+                let rows = query
+                    .limit(limit.saturating_add(1))
+                    .load::<(Article, User, bool)>($conn)
+                    .expect("Cannot load articles");
+                paging.page(limit, rows, None, key)
+            }
+        }
+        .map(|(article, author, favorited)| article.attach(author, favorited))
+    }};
+}
+
 #[derive(Deserialize, Default, Debug)]
 pub struct FindArticles {
     pub tag: Option<String>,
@@ -77,13 +148,23 @@ pub struct FindArticles {
     pub favorited: Option<String>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
+    /// `nextCursor` or `prevCursor` of a page, instead of `offset`
+    pub after: Option<String>,
+    pub before: Option<String>,
+}
+
+impl FindArticles {
+    pub fn paging(&self) -> Option<Paging> {
+        Paging::new(self.offset, self.after.as_deref(), self.before.as_deref())
+    }
 }
 
 pub fn find(
     conn: &PgConnection,
     params: &FindArticles,
+    paging: &Paging,
     user_id: Option<i32>,
-) -> (Vec<ArticleJson>, i64) {
+) -> Page<ArticleJson> {
     let mut query = articles::table
         .inner_join(users::table)
         .left_join(
@@ -98,10 +179,22 @@ pub fn find(
         ))
         .into_boxed();
     if let Some(ref author) = params.author {
-        query = query.filter(users::username.eq(author))
+        let author = match crate::db::users::find_by_username(conn, author) {
+            Some(author) => author.id,
+            None => return Page::empty(),
+        };
+        // their own articles and the ones they co-author
+        query = query.filter(
+            articles::author.eq(author).or(articles::id.eq_any(
+                article_authors::table
+                    .select(article_authors::article)
+                    .filter(article_authors::user.eq(author))
+                    .filter(article_authors::accepted_at.is_not_null()),
+            )),
+        )
     }
     if let Some(ref tag) = params.tag {
-        query = query.or_filter(articles::tag_list.contains(vec![tag]))
+        query = query.or_filter(articles::tag_list.contains(vec![tags::normalize(tag)]))
     }
     if let Some(ref favorited) = params.favorited {
         let result = users::table
@@ -116,28 +209,41 @@ pub fn find(
                 )));
             }
             Err(err) => match err {
-                diesel::result::Error::NotFound => return (vec![], 0),
+                diesel::result::Error::NotFound => return Page::empty(),
                 _ => panic!("Cannot load favorited user: {}", err),
             },
         }
     }
 
-    query
-        .order(articles::updated_at.desc()) // for article list order, update new at first
-        .offset_and_limit(
-            params.offset.unwrap_or(0),
-            params.limit.unwrap_or(DEFAULT_LIMIT),
-        )
-        .load_and_count::<(Article, User, bool)>(conn)
-        .map(|(res, count)| {
-            (
-                res.into_iter()
-                    .map(|(article, author, favorited)| article.attach(author, favorited))
-                    .collect(),
-                count,
-            )
-        })
-        .expect("Cannot load articles")
+    // drafts are only listed for their authors; last, so the tag `or_filter` can't bypass it
+    query = query.filter(
+        articles::status
+            .eq(Status::Published.as_str())
+            .or(articles::author.eq(user_id.unwrap_or(0)))
+            .or(articles::id.eq_any(authors::co_author_of(user_id.unwrap_or(0)))),
+    );
+    let mut page = load_page!(query, paging, params.limit.unwrap_or(DEFAULT_LIMIT), conn);
+    authors::attach_articles(conn, &mut page.items, user_id);
+    reactions::attach_articles(conn, &mut page.items, user_id);
+    bookmarks::attach_articles(conn, &mut page.items, user_id);
+    page
+}
+
+/// The article if `user_id` may see it, drafts only to their authors and the
+/// users invited to write them.
+pub fn find_visible(conn: &PgConnection, slug: &str, user_id: Option<i32>) -> Option<Article> {
+    let article = articles::table
+        .filter(articles::slug.eq(slug))
+        .first::<Article>(conn)
+        .map_err(|err| eprintln!("articles::find_visible: {}", err))
+        .ok()?;
+    if article.status() != Status::Published
+        && Some(article.author) != user_id
+        && !user_id.is_some_and(|id| authors::is_co_author(conn, article.id, id))
+    {
+        return None;
+    }
+    Some(article)
 }
 
 pub fn find_one(
@@ -145,35 +251,56 @@ pub fn find_one(
     slug: &str,
     user_id: Option<i32>,
 ) -> Option<ArticleProfileJson> {
-    let article = articles::table
-        .filter(articles::slug.eq(slug))
-        .first::<Article>(conn)
-        .map_err(|err| eprintln!("articles::find_one: {}", err))
-        .ok()?;
+    let article = find_visible(conn, slug, user_id)?;
 
     let favorited = user_id
         .map(|id| is_favorite(conn, &article, id))
         .unwrap_or(false);
 
-    Some(populate(conn, article, favorited, user_id.unwrap()))
+    Some(populate(conn, article, favorited, user_id.unwrap_or(0)))
 }
 
 #[derive(Deserialize, Default, Debug)]
 pub struct FeedArticles {
     limit: Option<i64>,
     offset: Option<i64>,
+    after: Option<String>,
+    before: Option<String>,
+}
+
+impl FeedArticles {
+    pub fn paging(&self) -> Option<Paging> {
+        Paging::new(self.offset, self.after.as_deref(), self.before.as_deref())
+    }
 }
 
 // select * from articles where author in (select followed from follows where follower = 7);
-// 1.followed user's post; 2.favorited
-pub fn feed(conn: &PgConnection, params: &FeedArticles, user_id: i32) -> Vec<ArticleJson> {
-    articles::table
+// 1.followed user's post, or co-authored; 2.favorited
+pub fn feed(
+    conn: &PgConnection,
+    params: &FeedArticles,
+    paging: &Paging,
+    user_id: i32,
+) -> Page<ArticleJson> {
+    let followed = || {
+        follows::table
+            .select(follows::followed)
+            .filter(follows::follower.eq(user_id))
+    };
+    let query = articles::table
         .filter(
-            articles::author.eq_any(
-                follows::table
-                    .select(follows::followed)
-                    .filter(follows::follower.eq(user_id)),
-            ),
+            articles::author.eq_any(followed()).or(articles::id.eq_any(
+                article_authors::table
+                    .select(article_authors::article)
+                    .filter(article_authors::accepted_at.is_not_null())
+                    .filter(article_authors::user.eq_any(followed())),
+            )),
+        )
+        .filter(
+            articles::status
+                .eq(Status::Published.as_str())
+                .or(articles::author.eq(user_id))
+                .or(articles::id.eq_any(authors::co_author_of(user_id))),
         )
         .inner_join(users::table)
         .left_join(
@@ -186,13 +313,12 @@ pub fn feed(conn: &PgConnection, params: &FeedArticles, user_id: i32) -> Vec<Art
             users::all_columns,
             favorites::user.nullable().is_not_null(),
         ))
-        .limit(params.limit.unwrap_or(DEFAULT_LIMIT))
-        .offset(params.offset.unwrap_or(0))
-        .load::<(Article, User, bool)>(conn)
-        .expect("Cannot load feed")
-        .into_iter()
-        .map(|(article, author, favorited)| article.attach(author, favorited))
-        .collect()
+        .into_boxed();
+    let mut page = load_page!(query, paging, params.limit.unwrap_or(DEFAULT_LIMIT), conn);
+    authors::attach_articles(conn, &mut page.items, Some(user_id));
+    reactions::attach_articles(conn, &mut page.items, Some(user_id));
+    bookmarks::attach_articles(conn, &mut page.items, Some(user_id));
+    page
 }
 
 pub fn favorite(conn: &PgConnection, slug: &str, user_id: i32) -> Option<ArticleProfileJson> {
@@ -235,38 +361,215 @@ pub struct UpdateArticleData {
     description: Option<String>,
     body: Option<String>,
     #[serde(skip)]
+    body_html: Option<String>,
+    #[serde(skip)]
     slug: Option<String>,
     #[serde(rename = "tagList")]
     tag_list: Vec<String>,
 }
 
+impl UpdateArticleData {
+    /// Normalize the tags, or say why they can't be used.
+    pub fn check_tags(&mut self, conn: &PgConnection) -> Result<(), &'static str> {
+        self.tag_list = tags::check(conn, &self.tag_list)?;
+        Ok(())
+    }
+}
+
+fn find_author(conn: &PgConnection, slug: &str) -> Result<i32, WriteError> {
+    articles::table
+        .select(articles::author)
+        .filter(articles::slug.eq(slug))
+        .get_result::<i32>(conn)
+        .optional()
+        .expect("Error loading article")
+        .ok_or(WriteError::NotFound)
+}
+
+/// An article whatever its status, for its author and the users invited to
+/// write it, before they accept too.
+pub fn find_invited(conn: &PgConnection, slug: &str, user_id: i32) -> Option<Article> {
+    load(conn, slug).ok().filter(|article| {
+        article.author == user_id || authors::is_invited(conn, article.id, user_id)
+    })
+}
+
+fn load(conn: &PgConnection, slug: &str) -> Result<Article, WriteError> {
+    articles::table
+        .filter(articles::slug.eq(slug))
+        .first::<Article>(conn)
+        .optional()
+        .expect("Error loading article")
+        .ok_or(WriteError::NotFound)
+}
+
+/// Only the author and the co-authors may update an article. Every update is
+/// kept as a revision. A new title makes a new slug unless `KEEP_SLUGS`, the
+/// old one still leads to the article.
 pub fn update(
     conn: &PgConnection,
     slug: &str,
     user_id: i32,
     mut data: UpdateArticleData,
-) -> Option<ArticleProfileJson> {
+) -> Result<ArticleProfileJson, WriteError> {
+    let current = load(conn, slug)?;
+    if !authors::can_edit(conn, &current, user_id) {
+        return Err(WriteError::Forbidden);
+    }
     if let Some(ref title) = data.title {
-        data.slug = Some(slugify(&title));
+        if *title != current.title && !CONFIG.keep_slugs {
+            data.slug = Some(slugify(&title));
+        }
+    }
+    data.body_html = data.body.as_deref().map(markdown::render);
+    let article = conn
+        .transaction::<_, diesel::result::Error, _>(|| {
+            let article = diesel::update(articles::table.find(current.id))
+                .set(&data)
+                .get_result::<Article>(conn)?;
+            if article.slug != current.slug {
+                record_slug(conn, &current.slug, &article)?;
+            }
+            tags::sync(conn, &article)?;
+            revisions::record(conn, &article, user_id)?;
+            Ok(article)
+        })
+        .map_err(|err| match err {
+            // the new slug, or a revision number should the lock be missed
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                WriteError::Conflict
+            }
+            err => panic!("Error updating article: {}", err),
+        })?;
+
+    let favorited = is_favorite(conn, &article, user_id);
+    Ok(populate(conn, article, favorited, user_id))
+}
+
+/// Keep `old` leading to the article. A slug it gets back is no longer old.
+fn record_slug(conn: &PgConnection, old: &str, article: &Article) -> QueryResult<()> {
+    diesel::insert_into(article_slugs::table)
+        .values((
+            article_slugs::slug.eq(old),
+            article_slugs::article.eq(article.id),
+        ))
+        .on_conflict(article_slugs::slug)
+        .do_update()
+        .set(article_slugs::article.eq(article.id))
+        .execute(conn)?;
+    diesel::delete(article_slugs::table.find(&article.slug)).execute(conn)?;
+    Ok(())
+}
+
+/// The slug of the article that had `slug` before its title changed.
+pub fn renamed(conn: &PgConnection, slug: &str) -> Option<String> {
+    article_slugs::table
+        .inner_join(articles::table)
+        .select(articles::slug)
+        .filter(article_slugs::slug.eq(slug))
+        .get_result(conn)
+        .optional()
+        .expect("Error loading article slug")
+}
+
+/// Bring back the content of revision `number`, as a new revision.
+pub fn restore(
+    conn: &PgConnection,
+    slug: &str,
+    user_id: i32,
+    number: i32,
+) -> Result<ArticleProfileJson, WriteError> {
+    let article = load(conn, slug)?;
+    if !authors::can_edit(conn, &article, user_id) {
+        return Err(WriteError::Forbidden);
+    }
+    let (revision, _) = revisions::find(conn, article.id, number).ok_or(WriteError::NotFound)?;
+    let data = UpdateArticleData {
+        title: Some(revision.title),
+        description: Some(revision.description),
+        body: Some(revision.body),
+        body_html: None,
+        slug: None,
+        // tags blocked since are left out
+        tag_list: tags::allowed(conn, &revision.tag_list),
+    };
+    update(conn, slug, user_id, data)
+}
+
+/// Return the deleted article. Co-authors may not delete it.
+pub fn delete(conn: &PgConnection, slug: &str, auth: &Auth) -> Result<Article, WriteError> {
+    if !policy::can_delete_article(auth, find_author(conn, slug)?) {
+        return Err(WriteError::Forbidden);
     }
-    // TODO: check for not_found
-    let article = diesel::update(articles::table.filter(articles::slug.eq(slug)))
-        .set(&data)
+    let article = diesel::delete(articles::table.filter(articles::slug.eq(slug)))
         .get_result(conn)
-        .expect("Error loading article");
+        .expect("Error deleting article");
+    Ok(article)
+}
+
+/// Publish a draft now, or schedule it when `at` is in the future. Only the
+/// author and the co-authors may; a published article stays as it is.
+pub fn publish(
+    conn: &PgConnection,
+    slug: &str,
+    user_id: i32,
+    at: Option<DateTime<Utc>>,
+) -> Result<ArticleProfileJson, WriteError> {
+    if !authors::can_edit(conn, &load(conn, slug)?, user_id) {
+        return Err(WriteError::Forbidden);
+    }
+    let now = Utc::now();
+    let (status, published_at) = match at {
+        Some(at) if at > now => (Status::Scheduled, at),
+        _ => (Status::Published, now),
+    };
+    let article = diesel::update(
+        articles::table
+            .filter(articles::slug.eq(slug))
+            .filter(articles::status.ne(Status::Published.as_str())),
+    )
+    .set((
+        articles::status.eq(status.as_str()),
+        articles::published_at.eq(published_at),
+    ))
+    .get_result::<Article>(conn)
+    .optional()
+    .expect("Error publishing article");
+    let article = match article {
+        Some(article) => article,
+        None => articles::table
+            .filter(articles::slug.eq(slug))
+            .first::<Article>(conn)
+            .expect("Error loading article"),
+    };
 
     let favorited = is_favorite(conn, &article, user_id);
-    Some(populate(conn, article, favorited, user_id))
+    Ok(populate(conn, article, favorited, user_id))
 }
 
-pub fn delete(conn: &PgConnection, slug: &str, user_id: i32) {
-    let result = diesel::delete(
-        articles::table.filter(articles::slug.eq(slug).and(articles::author.eq(user_id))),
+/// Publish the scheduled articles whose time has come, returns how many.
+pub fn publish_due(conn: &PgConnection) -> QueryResult<usize> {
+    diesel::update(
+        articles::table
+            .filter(articles::status.eq(Status::Scheduled.as_str()))
+            .filter(articles::published_at.le(Utc::now())),
     )
-    .execute(conn);
-    if let Err(err) = result {
-        eprintln!("articles::delete: {}", err);
+    .set(articles::status.eq(Status::Published.as_str()))
+    .execute(conn)
+}
+
+/// Render the bodies written before they were rendered on write, returns how many.
+pub fn render_bodies(conn: &PgConnection) -> QueryResult<usize> {
+    let missing = articles::table
+        .select((articles::id, articles::body))
+        .filter(articles::body_html.is_null())
+        .load::<(i32, String)>(conn)?;
+    for (id, body) in &missing {
+        diesel::update(articles::table.find(id))
+            .set(articles::body_html.eq(markdown::render(body)))
+            .execute(conn)?;
     }
+    Ok(missing.len())
 }
 
 fn is_favorite(conn: &PgConnection, article: &Article, user_id: i32) -> bool {
@@ -289,14 +592,16 @@ fn populate(
         .get_result::<User>(conn)
         .expect("Error loading author");
     let following = is_following(&conn, &author, user_id);
-    article.attach_profile(author, favorited, following)
-}
-
-pub fn tags(conn: &PgConnection) -> Vec<String> {
-    articles::table
-        .select(diesel::dsl::sql("distinct unnest(tag_list)"))
-        .load::<String>(conn)
-        .expect("Cannot load tags")
+    let mut authors = authors::of_articles(conn, &[article.id], Some(user_id));
+    let mut reactions = reactions::of_articles(conn, &[article.id], Some(user_id));
+    let mut json = article.attach_profile(author, favorited, following);
+    json.authors = authors.remove(&json.id).unwrap_or_default();
+    json.reactions = reactions.remove(&json.id).unwrap_or_default();
+    // 0 is nobody, see `find_one`
+    if user_id != 0 {
+        json.bookmarked = Some(bookmarks::is_bookmarked(conn, user_id, json.id));
+    }
+    json
 }
 
 #[cfg(test)]
@@ -0,0 +1,168 @@
+//! The append-only trail of logins, account changes, deletions and admin actions.
+use crate::auth::Auth;
+use crate::db::OffsetLimit;
+use crate::models::audit_event::AuditEvent;
+use crate::schema::audit_events;
+use chrono::{DateTime, Utc};
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::net::IpAddr;
+
+pub const LOGIN: &str = "login";
+pub const LOGIN_FAILED: &str = "login.failed";
+pub const USER_UPDATE: &str = "user.update";
+pub const PASSWORD_CHANGE: &str = "user.password";
+pub const PASSWORD_RESET: &str = "user.password_reset";
+pub const USER_ROLE: &str = "user.role";
+pub const USER_UNLOCK: &str = "user.unlock";
+pub const USER_DELETE: &str = "user.delete";
+pub const ARTICLE_DELETE: &str = "article.delete";
+pub const COMMENT_DELETE: &str = "comment.delete";
+pub const TAG_RENAME: &str = "tag.rename";
+pub const TAG_MERGE: &str = "tag.merge";
+pub const TAG_BLOCK: &str = "tag.block";
+pub const TAG_UNBLOCK: &str = "tag.unblock";
+
+/// Who did it. The name is kept as it was, the user may be renamed or gone later.
+#[derive(Debug, Clone, Default)]
+pub struct Actor {
+    pub id: Option<i32>,
+    pub name: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+impl Actor {
+    pub fn new(id: i32, name: &str, ip: Option<IpAddr>) -> Actor {
+        Actor {
+            id: Some(id),
+            name: Some(name.to_string()),
+            ip,
+        }
+    }
+
+    pub fn from_auth(auth: &Auth, ip: Option<IpAddr>) -> Actor {
+        Actor::new(auth.id, &auth.username, ip)
+    }
+
+    /// Someone with database access, through `realworld-cli`.
+    pub fn cli() -> Actor {
+        Actor {
+            id: None,
+            name: Some(format!(
+                "cli:{}",
+                std::env::var("USER").unwrap_or_else(|_| String::from("unknown"))
+            )),
+            ip: None,
+        }
+    }
+}
+
+/// Append an event. A failure is logged but doesn't stop the action itself.
+pub fn record(conn: &PgConnection, actor: &Actor, action: &str, target: Option<&str>, diff: Value) {
+    diesel::insert_into(audit_events::table)
+        .values((
+            audit_events::actor.eq(actor.id),
+            audit_events::actor_name.eq(actor.name.as_ref()),
+            audit_events::action.eq(action),
+            audit_events::target.eq(target),
+            audit_events::ip.eq(actor.ip.map(|ip| ip.to_string())),
+            audit_events::diff.eq(diff),
+        ))
+        .execute(conn)
+        .map_err(|err| eprintln!("audit::record {}: {}", action, err))
+        .ok();
+}
+
+/// `{"field": [old, new]}` for the fields that differ between two objects,
+/// a missing field counts as null. Against `{}` it records a deleted row.
+pub fn diff(before: &Value, after: &Value) -> Value {
+    let empty = Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+    let changes: Map<String, Value> = before
+        .keys()
+        .chain(after.keys())
+        .filter_map(|field| {
+            let old = before.get(field).unwrap_or(&Value::Null);
+            let new = after.get(field).unwrap_or(&Value::Null);
+            if old == new {
+                None
+            } else {
+                Some((field.clone(), json!([old, new])))
+            }
+        })
+        .collect();
+    Value::Object(changes)
+}
+
+const DEFAULT_LIMIT: i64 = 50;
+
+#[derive(Deserialize, Default, Debug)]
+pub struct FindEvents {
+    pub actor: Option<i32>,
+    /// `user` also finds `user.delete`, `user.role`...
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// The events matching all given filters, the newest first, and their count.
+pub fn find(conn: &PgConnection, params: &FindEvents) -> (Vec<AuditEvent>, i64) {
+    let mut query = audit_events::table.into_boxed();
+    if let Some(actor) = params.actor {
+        query = query.filter(audit_events::actor.eq(actor));
+    }
+    if let Some(action) = &params.action {
+        query = query.filter(
+            audit_events::action
+                .eq(action.clone())
+                .or(audit_events::action.like(format!("{}.%", action))),
+        );
+    }
+    if let Some(target) = &params.target {
+        query = query.filter(audit_events::target.eq(target.clone()));
+    }
+    if let Some(since) = params.since {
+        query = query.filter(audit_events::created_at.ge(since));
+    }
+    if let Some(until) = params.until {
+        query = query.filter(audit_events::created_at.lt(until));
+    }
+    query
+        .order(audit_events::id.desc())
+        .offset_and_limit(
+            params.offset.unwrap_or(0),
+            params.limit.unwrap_or(DEFAULT_LIMIT),
+        )
+        .load_and_count::<AuditEvent>(conn)
+        .expect("Cannot load audit events")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let before = json!({ "username": "jake", "email": "jake@jake.jake", "bio": null });
+        let after = json!({ "username": "jake", "email": "jake@example.com", "bio": "I work" });
+        assert_eq!(
+            diff(&before, &after),
+            json!({
+                "email": ["jake@jake.jake", "jake@example.com"],
+                "bio": [null, "I work"],
+            })
+        );
+        assert_eq!(diff(&after, &after), json!({}));
+        assert_eq!(
+            diff(&json!({ "title": "Dragons" }), &json!({})),
+            json!({ "title": ["Dragons", null] })
+        );
+    }
+}
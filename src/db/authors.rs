@@ -0,0 +1,260 @@
+//! Co-authors are in `article_authors`, the user who wrote an article stays
+//! its author in `articles.author` and alone invites, removes and deletes.
+//! An invited user only sees the title in their invitations, once they accept
+//! they read and edit the article too.
+use crate::config::DATE_FORMAT;
+use crate::models::article::{Article, ArticleJson};
+use crate::models::user::PubProfile;
+use crate::policy;
+use crate::schema::article_authors;
+use crate::schema::articles;
+use crate::schema::users;
+use chrono::{DateTime, Utc};
+use diesel;
+use diesel::dsl::{exists, Eq, Filter, IsNotNull, Select};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Array, Bool, Int4, Nullable, Text};
+use serde::Serialize;
+use std::collections::HashMap;
+
+type ArticlesOf = Filter<
+    Select<article_authors::table, article_authors::article>,
+    Eq<article_authors::user, i32>,
+>;
+
+/// The articles `user_id` accepted to write, to filter with `articles::id.eq_any(..)`.
+pub fn co_author_of(user_id: i32) -> Filter<ArticlesOf, IsNotNull<article_authors::accepted_at>> {
+    article_authors::table
+        .select(article_authors::article)
+        .filter(article_authors::user.eq(user_id))
+        .filter(article_authors::accepted_at.is_not_null())
+}
+
+/// Whether `user_id` is invited to write the article, accepted or not.
+pub fn is_invited(conn: &PgConnection, article_id: i32, user_id: i32) -> bool {
+    diesel::select(exists(article_authors::table.find((article_id, user_id))))
+        .get_result(conn)
+        .expect("Error loading article authors")
+}
+
+/// Whether `user_id` accepted to write the article.
+pub fn is_co_author(conn: &PgConnection, article_id: i32, user_id: i32) -> bool {
+    diesel::select(exists(
+        article_authors::table
+            .find((article_id, user_id))
+            .filter(article_authors::accepted_at.is_not_null()),
+    ))
+    .get_result(conn)
+    .expect("Error loading article authors")
+}
+
+/// The author and the co-authors who accepted may edit an article.
+pub fn can_edit(conn: &PgConnection, article: &Article, user_id: i32) -> bool {
+    policy::can_edit_article(
+        user_id,
+        article.author,
+        article.author != user_id && is_co_author(conn, article.id, user_id),
+    )
+}
+
+#[derive(QueryableByName, Debug)]
+struct AuthorRow {
+    #[sql_type = "Int4"]
+    article: i32,
+    #[sql_type = "Int4"]
+    id: i32,
+    #[sql_type = "Text"]
+    username: String,
+    #[sql_type = "Nullable<Text>"]
+    bio: Option<String>,
+    #[sql_type = "Nullable<Text>"]
+    image: Option<String>,
+    #[sql_type = "Bool"]
+    following: bool,
+}
+
+/// The rows by article, in their order.
+fn group(rows: Vec<AuthorRow>) -> HashMap<i32, Vec<PubProfile>> {
+    let mut grouped: HashMap<i32, Vec<PubProfile>> = HashMap::new();
+    for row in rows {
+        grouped.entry(row.article).or_default().push(PubProfile {
+            id: row.id,
+            username: row.username,
+            bio: row.bio,
+            image: row.image,
+            following: row.following,
+        });
+    }
+    grouped
+}
+
+/// The authors of each article, the author first then the co-authors who
+/// accepted in the order they did.
+pub fn of_articles(
+    conn: &PgConnection,
+    ids: &[i32],
+    user_id: Option<i32>,
+) -> HashMap<i32, Vec<PubProfile>> {
+    if ids.is_empty() {
+        return HashMap::new();
+    }
+    let rows = sql_query(
+        "SELECT authors.article, users.id, users.username, users.bio, users.image, \
+         EXISTS(SELECT 1 FROM follows WHERE follows.follower = $2 \
+           AND follows.followed = users.id) AS following \
+         FROM (SELECT id AS article, author AS \"user\", NULL::timestamptz AS accepted_at \
+           FROM articles WHERE id = ANY($1) \
+           UNION ALL SELECT article, \"user\", accepted_at FROM article_authors \
+           WHERE article = ANY($1) AND accepted_at IS NOT NULL) AS authors \
+         JOIN users ON users.id = authors.\"user\" \
+         ORDER BY authors.article, authors.accepted_at NULLS FIRST, users.id",
+    )
+    .bind::<Array<Int4>, _>(ids)
+    .bind::<Int4, _>(user_id.unwrap_or(0))
+    .load(conn)
+    .expect("Cannot load article authors");
+    group(rows)
+}
+
+/// Fill in the authors of a list of articles.
+pub fn attach_articles(conn: &PgConnection, articles: &mut [ArticleJson], user_id: Option<i32>) {
+    let ids: Vec<i32> = articles.iter().map(|article| article.id).collect();
+    let mut authors = of_articles(conn, &ids, user_id);
+    for article in articles {
+        article.authors = authors.remove(&article.id).unwrap_or_default();
+    }
+}
+
+/// A co-author as the author sees them, invitations included.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CoAuthor {
+    pub username: String,
+    pub image: Option<String>,
+    pub accepted: bool,
+    pub invited_at: String,
+}
+
+/// The co-authors of an article and the users invited, the first invited first.
+pub fn list(conn: &PgConnection, article_id: i32) -> Vec<CoAuthor> {
+    article_authors::table
+        .inner_join(users::table)
+        .select((
+            users::username,
+            users::image,
+            article_authors::accepted_at,
+            article_authors::created_at,
+        ))
+        .filter(article_authors::article.eq(article_id))
+        .order((article_authors::created_at.asc(), users::id.asc()))
+        .load::<(String, Option<String>, Option<DateTime<Utc>>, DateTime<Utc>)>(conn)
+        .expect("Cannot load article authors")
+        .into_iter()
+        .map(|(username, image, accepted_at, created_at)| CoAuthor {
+            username,
+            image,
+            accepted: accepted_at.is_some(),
+            invited_at: created_at.format(DATE_FORMAT).to_string(),
+        })
+        .collect()
+}
+
+/// Invite a user to write an article, again changes nothing.
+pub fn invite(conn: &PgConnection, article_id: i32, user_id: i32) -> QueryResult<()> {
+    diesel::insert_into(article_authors::table)
+        .values((
+            article_authors::article.eq(article_id),
+            article_authors::user.eq(user_id),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Accept an invitation, false if there is none. Accepting again changes nothing.
+pub fn accept(conn: &PgConnection, article_id: i32, user_id: i32) -> QueryResult<bool> {
+    if !is_invited(conn, article_id, user_id) {
+        return Ok(false);
+    }
+    diesel::update(
+        article_authors::table
+            .find((article_id, user_id))
+            .filter(article_authors::accepted_at.is_null()),
+    )
+    .set(article_authors::accepted_at.eq(Utc::now()))
+    .execute(conn)?;
+    Ok(true)
+}
+
+/// Take a co-author or an invitation off an article, false if there was none.
+pub fn remove(conn: &PgConnection, article_id: i32, user_id: i32) -> QueryResult<bool> {
+    let removed =
+        diesel::delete(article_authors::table.find((article_id, user_id))).execute(conn)?;
+    Ok(removed > 0)
+}
+
+/// An article a user is invited to write and hasn't accepted yet.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Invitation {
+    pub slug: String,
+    pub title: String,
+    /// who invited, the author of the article
+    pub author: String,
+    pub invited_at: String,
+}
+
+/// The invitations of a user, the latest first.
+pub fn invitations(conn: &PgConnection, user_id: i32) -> Vec<Invitation> {
+    article_authors::table
+        .inner_join(articles::table.inner_join(users::table))
+        .select((
+            articles::slug,
+            articles::title,
+            users::username,
+            article_authors::created_at,
+        ))
+        .filter(article_authors::user.eq(user_id))
+        .filter(article_authors::accepted_at.is_null())
+        .order((
+            article_authors::created_at.desc(),
+            article_authors::article.desc(),
+        ))
+        .load::<(String, String, String, DateTime<Utc>)>(conn)
+        .expect("Cannot load invitations")
+        .into_iter()
+        .map(|(slug, title, author, created_at)| Invitation {
+            slug,
+            title,
+            author,
+            invited_at: created_at.format(DATE_FORMAT).to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(article: i32, id: i32) -> AuthorRow {
+        AuthorRow {
+            article,
+            id,
+            username: format!("user{}", id),
+            bio: None,
+            image: None,
+            following: false,
+        }
+    }
+
+    #[test]
+    fn test_group() {
+        let grouped = group(vec![row(1, 7), row(1, 3), row(2, 3)]);
+        let ids = |article| -> Vec<i32> { grouped[&article].iter().map(|a| a.id).collect() };
+        assert_eq!(ids(1), vec![7, 3]);
+        assert_eq!(ids(2), vec![3]);
+        assert!(!grouped.contains_key(&3));
+    }
+}
@@ -0,0 +1,229 @@
+//! Bookmarks are a private reading list, unlike favorites nobody else sees
+//! them, not even their count.
+use crate::db::authors;
+use crate::db::reactions;
+use crate::db::{Cursor, OffsetLimit, Page, Paging};
+use crate::models::article::{Article, ArticleJson, Status};
+use crate::models::bookmark::{Bookmark, BookmarkJson};
+use crate::models::user::User;
+use crate::schema::articles;
+use crate::schema::bookmarks;
+use crate::schema::favorites;
+use crate::schema::users;
+use diesel;
+use diesel::dsl::exists;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Int4, Nullable, Text};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LIMIT: i64 = 20;
+
+/// What is given is changed, an empty folder or note takes it away.
+#[derive(AsChangeset, Default, Debug)]
+#[table_name = "bookmarks"]
+pub struct BookmarkChanges {
+    pub folder: Option<Option<String>>,
+    pub note: Option<Option<String>>,
+}
+
+impl BookmarkChanges {
+    pub fn new(folder: Option<String>, note: Option<String>) -> BookmarkChanges {
+        let clean = |text: String| Some(text.trim().to_string()).filter(|text| !text.is_empty());
+        BookmarkChanges {
+            folder: folder.map(clean),
+            note: note.map(clean),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.folder.is_none() && self.note.is_none()
+    }
+}
+
+/// Bookmark an article, or change the folder and note of its bookmark.
+pub fn add(
+    conn: &PgConnection,
+    user_id: i32,
+    article_id: i32,
+    changes: &BookmarkChanges,
+) -> QueryResult<Bookmark> {
+    let insert = diesel::insert_into(bookmarks::table).values((
+        bookmarks::user.eq(user_id),
+        bookmarks::article.eq(article_id),
+        bookmarks::folder.eq(changes.folder.clone().flatten()),
+        bookmarks::note.eq(changes.note.clone().flatten()),
+    ));
+    if changes.is_empty() {
+        insert.on_conflict_do_nothing().execute(conn)?;
+    } else {
+        insert
+            .on_conflict((bookmarks::user, bookmarks::article))
+            .do_update()
+            .set(changes)
+            .execute(conn)?;
+    }
+    bookmarks::table
+        .find((user_id, article_id))
+        .get_result(conn)
+}
+
+pub fn remove(conn: &PgConnection, user_id: i32, article_id: i32) -> QueryResult<usize> {
+    diesel::delete(bookmarks::table.find((user_id, article_id))).execute(conn)
+}
+
+pub fn is_bookmarked(conn: &PgConnection, user_id: i32, article_id: i32) -> bool {
+    diesel::select(exists(bookmarks::table.find((user_id, article_id))))
+        .get_result(conn)
+        .expect("Error loading bookmarked")
+}
+
+/// Which of the articles `ids` the user bookmarked.
+pub fn bookmarked(conn: &PgConnection, user_id: i32, ids: &[i32]) -> Vec<i32> {
+    bookmarks::table
+        .select(bookmarks::article)
+        .filter(bookmarks::user.eq(user_id))
+        .filter(bookmarks::article.eq_any(ids))
+        .load::<i32>(conn)
+        .expect("Error loading bookmarked")
+}
+
+/// Fill in `bookmarked` of a list of articles, only for the user it is about.
+pub fn attach_articles(conn: &PgConnection, articles: &mut [ArticleJson], user_id: Option<i32>) {
+    if let Some(user_id) = user_id {
+        let ids: Vec<i32> = articles.iter().map(|article| article.id).collect();
+        let bookmarked = bookmarked(conn, user_id, &ids);
+        for article in articles {
+            article.bookmarked = Some(bookmarked.contains(&article.id));
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct FindBookmarks {
+    pub folder: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// `nextCursor` or `prevCursor` of a page, instead of `offset`
+    pub after: Option<String>,
+    pub before: Option<String>,
+}
+
+impl FindBookmarks {
+    pub fn paging(&self) -> Option<Paging> {
+        Paging::new(self.offset, self.after.as_deref(), self.before.as_deref())
+    }
+}
+
+/// The bookmarks of a user, the latest first.
+pub fn list(
+    conn: &PgConnection,
+    user_id: i32,
+    params: &FindBookmarks,
+    paging: &Paging,
+) -> Page<BookmarkJson<ArticleJson>> {
+    let mut query = bookmarks::table
+        .inner_join(articles::table)
+        .inner_join(users::table.on(users::id.eq(articles::author)))
+        .left_join(
+            favorites::table.on(articles::id
+                .eq(favorites::article)
+                .and(favorites::user.eq(user_id))),
+        )
+        .select((
+            bookmarks::all_columns,
+            articles::all_columns,
+            users::all_columns,
+            favorites::user.nullable().is_not_null(),
+        ))
+        .filter(bookmarks::user.eq(user_id))
+        // an article back to draft is out of reach until it's published again
+        .filter(
+            articles::status
+                .eq(Status::Published.as_str())
+                .or(articles::author.eq(user_id))
+                .or(articles::id.eq_any(authors::co_author_of(user_id))),
+        )
+        .into_boxed();
+    if let Some(ref folder) = params.folder {
+        query = query.filter(bookmarks::folder.eq(folder.trim()));
+    }
+    if let Some(keyset) = paging.keyset("bookmarks.created_at", "bookmarks.article") {
+        query = query.filter(keyset);
+    }
+    query = if paging.is_reversed() {
+        query.order((bookmarks::created_at.asc(), bookmarks::article.asc()))
+    } else {
+        query.order((bookmarks::created_at.desc(), bookmarks::article.desc()))
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    let key = |(bookmark, _, _, _): &(Bookmark, Article, User, bool)| Cursor {
+        at: Some(bookmark.created_at),
+        id: bookmark.article,
+    };
+    let page = match paging {
+        Paging::Offset(offset) => {
+            let (rows, count) = query
+                .offset_and_limit(*offset, limit)
+                .load_and_count::<(Bookmark, Article, User, bool)>(conn)
+                .expect("Cannot load bookmarks");
+            paging.page(limit, rows, Some(count), key)
+        }
+        _ => {
+            let rows = query
+                .limit(limit.saturating_add(1))
+                .load::<(Bookmark, Article, User, bool)>(conn)
+                .expect("Cannot load bookmarks");
+            paging.page(limit, rows, None, key)
+        }
+    };
+    let mut page = page.map(|(bookmark, article, author, favorited)| {
+        let mut article = article.attach(author, favorited);
+        article.bookmarked = Some(true);
+        bookmark.attach(article)
+    });
+    let ids: Vec<i32> = page.items.iter().map(|b| b.article.id).collect();
+    let mut authors = authors::of_articles(conn, &ids, Some(user_id));
+    let mut reactions = reactions::of_articles(conn, &ids, Some(user_id));
+    for bookmark in &mut page.items {
+        bookmark.article.authors = authors.remove(&bookmark.article.id).unwrap_or_default();
+        bookmark.article.reactions = reactions.remove(&bookmark.article.id).unwrap_or_default();
+    }
+    page
+}
+
+#[derive(QueryableByName, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Folder {
+    /// None for the bookmarks in no folder
+    #[sql_type = "Nullable<Text>"]
+    pub folder: Option<String>,
+    #[sql_type = "BigInt"]
+    pub bookmarks_count: i64,
+}
+
+/// The folders of a user by name, and how many bookmarks are in each.
+pub fn folders(conn: &PgConnection, user_id: i32) -> Vec<Folder> {
+    sql_query(
+        "SELECT folder, COUNT(*) AS bookmarks_count FROM bookmarks \
+         WHERE \"user\" = $1 GROUP BY folder ORDER BY folder NULLS FIRST",
+    )
+    .bind::<Int4, _>(user_id)
+    .load(conn)
+    .expect("Cannot load bookmark folders")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bookmark_changes() {
+        let changes = BookmarkChanges::new(Some(String::from(" Rust ")), Some(String::from(" ")));
+        assert_eq!(changes.folder, Some(Some(String::from("Rust"))));
+        assert_eq!(changes.note, Some(None));
+        assert!(!changes.is_empty());
+        assert!(BookmarkChanges::new(None, None).is_empty());
+    }
+}
@@ -1,11 +1,20 @@
+use crate::auth::Auth;
+use crate::db::reactions;
+use crate::db::{Cursor, OffsetLimit, Page, Paging, WriteError};
+use crate::markdown;
 use crate::models::comment::{Comment, CommentJson};
 use crate::models::user::User;
+use crate::policy;
 use crate::schema::articles;
 use crate::schema::comments;
 use crate::schema::users;
 use diesel;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
+use serde::Deserialize;
+
+/// page size with a cursor, without one all comments are listed
+const DEFAULT_LIMIT: i64 = 20;
 
 #[derive(Insertable)]
 #[table_name = "comments"]
@@ -13,6 +22,7 @@ struct NewComment<'a> {
     body: &'a str,
     author: i32,
     article: i32,
+    body_html: &'a str,
 }
 
 pub fn create(conn: &PgConnection, author: i32, slug: &str, body: &str) -> CommentJson {
@@ -25,6 +35,7 @@ pub fn create(conn: &PgConnection, author: i32, slug: &str, body: &str) -> Comme
         body,
         author,
         article: article_id,
+        body_html: &markdown::render(body),
     };
 
     let author = users::table
@@ -39,40 +50,134 @@ pub fn create(conn: &PgConnection, author: i32, slug: &str, body: &str) -> Comme
         .attach(author)
 }
 
-pub fn find_by_slug(conn: &PgConnection, slug: &str) -> Vec<CommentJson> {
-    let result = comments::table
+#[derive(Deserialize, Default, Debug)]
+pub struct FindComments {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// `nextCursor` or `prevCursor` of a page, instead of `offset`
+    pub after: Option<String>,
+    pub before: Option<String>,
+}
+
+impl FindComments {
+    pub fn paging(&self) -> Option<Paging> {
+        Paging::new(self.offset, self.after.as_deref(), self.before.as_deref())
+    }
+}
+
+/// The comments of an article, the newest first, reactions as seen by `user_id`.
+pub fn find_by_slug(
+    conn: &PgConnection,
+    slug: &str,
+    params: &FindComments,
+    paging: &Paging,
+    user_id: Option<i32>,
+) -> Page<CommentJson> {
+    let mut query = comments::table
         .inner_join(articles::table)
         .inner_join(users::table)
         .select((comments::all_columns, users::all_columns))
         .filter(articles::slug.eq(slug))
-        .order(comments::updated_at.desc()) //andrew
-        .get_results::<(Comment, User)>(conn)
-        .expect("Cannot load comments");
-
-    result
-        .into_iter()
-        .map(|(comment, author)| comment.attach(author))
-        .collect()
+        .into_boxed();
+    if let Some(keyset) = paging.keyset("comments.created_at", "comments.id") {
+        query = query.filter(keyset);
+    }
+    query = if paging.is_reversed() {
+        query.order((comments::created_at.asc(), comments::id.asc()))
+    } else {
+        query.order((comments::created_at.desc(), comments::id.desc()))
+    };
+    let key = |(comment, _): &(Comment, User)| Cursor {
+        at: Some(comment.created_at),
+        id: comment.id,
+    };
+    let page = match (paging, params.limit) {
+        (Paging::Offset(offset), Some(limit)) => {
+            let (rows, count) = query
+                .offset_and_limit(*offset, limit)
+                .load_and_count::<(Comment, User)>(conn)
+                .expect("Cannot load comments");
+            paging.page(limit, rows, Some(count), key)
+        }
+        (Paging::Offset(offset), None) => {
+            let rows = query
+                .offset(*offset)
+                .load::<(Comment, User)>(conn)
+                .expect("Cannot load comments");
+            let count = Some(offset + rows.len() as i64);
+            paging.page(0, rows, count, key)
+        }
+        (_, limit) => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT);
+            let rows = query
+                .limit(limit.saturating_add(1))
+                .load::<(Comment, User)>(conn)
+                .expect("Cannot load comments");
+            paging.page(limit, rows, None, key)
+        }
+    };
+    let mut page = page.map(|(comment, author)| comment.attach(author));
+    reactions::attach_comments(conn, &mut page.items, user_id);
+    page
 }
 
-pub fn delete(conn: &PgConnection, author: i32, slug: &str, comment_id: i32) {
-    use diesel::dsl::exists;
-    use diesel::select;
-
-    let belongs_to_author_result = select(exists(
-        articles::table.filter(articles::slug.eq(slug).and(articles::author.eq(author))),
-    ))
-    .get_result::<bool>(conn);
+/// A comment of the article `slug`, reactions as seen by `user_id`.
+pub fn find_one(
+    conn: &PgConnection,
+    slug: &str,
+    comment_id: i32,
+    user_id: Option<i32>,
+) -> Option<CommentJson> {
+    let (comment, author) = comments::table
+        .inner_join(articles::table)
+        .inner_join(users::table)
+        .select((comments::all_columns, users::all_columns))
+        .filter(comments::id.eq(comment_id))
+        .filter(articles::slug.eq(slug))
+        .get_result::<(Comment, User)>(conn)
+        .optional()
+        .expect("Error loading comment")?;
+    let mut reactions = reactions::of_comments(conn, &[comment.id], user_id);
+    let mut json = comment.attach(author);
+    json.reactions = reactions.remove(&json.id).unwrap_or_default();
+    Some(json)
+}
 
-    if let Err(err) = belongs_to_author_result {
-        match err {
-            diesel::result::Error::NotFound => return,
-            _ => panic!("Cannot find article by author: {}", err),
-        }
+/// Return the deleted comment.
+pub fn delete(
+    conn: &PgConnection,
+    auth: &Auth,
+    slug: &str,
+    comment_id: i32,
+) -> Result<Comment, WriteError> {
+    let (comment_author, article_author) = comments::table
+        .inner_join(articles::table)
+        .select((comments::author, articles::author))
+        .filter(comments::id.eq(comment_id))
+        .filter(articles::slug.eq(slug))
+        .get_result::<(i32, i32)>(conn)
+        .optional()
+        .expect("Error loading comment")
+        .ok_or(WriteError::NotFound)?;
+    if !policy::can_delete_comment(auth, comment_author, article_author) {
+        return Err(WriteError::Forbidden);
     }
+    let comment = diesel::delete(comments::table.filter(comments::id.eq(comment_id)))
+        .get_result(conn)
+        .expect("Error deleting comment");
+    Ok(comment)
+}
 
-    let result = diesel::delete(comments::table.filter(comments::id.eq(comment_id))).execute(conn);
-    if let Err(err) = result {
-        eprintln!("comments::delete: {}", err);
+/// Render the bodies written before they were rendered on write, returns how many.
+pub fn render_bodies(conn: &PgConnection) -> QueryResult<usize> {
+    let missing = comments::table
+        .select((comments::id, comments::body))
+        .filter(comments::body_html.is_null())
+        .load::<(i32, String)>(conn)?;
+    for (id, body) in &missing {
+        diesel::update(comments::table.find(id))
+            .set(comments::body_html.eq(markdown::render(body)))
+            .execute(conn)?;
     }
+    Ok(missing.len())
 }
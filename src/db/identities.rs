@@ -0,0 +1,84 @@
+use crate::db::{generate_secret, users};
+use crate::models::user::User;
+use crate::oidc::Identity;
+use crate::schema::{user_identities, users as users_table};
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use rand::{thread_rng, Rng};
+
+pub enum IdentityError {
+    /// a new user can't be made without an address
+    NoEmail,
+    /// a local account has the address, and the provider didn't verify it
+    EmailTaken,
+}
+
+/// The user behind an external identity. On its first login it is linked to
+/// the local account with the same verified email, or a new user is made.
+pub fn find_or_provision(conn: &PgConnection, identity: &Identity) -> Result<User, IdentityError> {
+    let linked = user_identities::table
+        .inner_join(users_table::table)
+        .filter(user_identities::issuer.eq(&identity.issuer))
+        .filter(user_identities::subject.eq(&identity.subject))
+        .select(users_table::all_columns)
+        .get_result::<User>(conn)
+        .optional()
+        .expect("Error loading user identity");
+    if let Some(user) = linked {
+        return Ok(user);
+    }
+
+    let email = identity.email.as_ref().ok_or(IdentityError::NoEmail)?;
+    let existing = users_table::table
+        .filter(users_table::email.eq(email))
+        .get_result::<User>(conn)
+        .optional()
+        .expect("Error loading user");
+    let user = match existing {
+        Some(user) if identity.email_verified => user,
+        Some(_) => return Err(IdentityError::EmailTaken),
+        None => provision(conn, identity, email)?,
+    };
+
+    diesel::insert_into(user_identities::table)
+        .values((
+            user_identities::user.eq(user.id),
+            user_identities::issuer.eq(&identity.issuer),
+            user_identities::subject.eq(&identity.subject),
+        ))
+        .execute(conn)
+        .expect("Error linking user identity");
+    log::info!(
+        "user {} linked to {} at {}",
+        user.id,
+        identity.subject,
+        identity.issuer
+    );
+    Ok(user)
+}
+
+/// A new user with a random password, it logs in through the provider only.
+fn provision(conn: &PgConnection, identity: &Identity, email: &str) -> Result<User, IdentityError> {
+    let base = identity
+        .username
+        .clone()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or(email).to_string());
+    let mut username = base.clone();
+    loop {
+        match users::create(conn, &username, email, &generate_secret()) {
+            Ok(user) => {
+                if identity.email_verified {
+                    users::verify_email(conn, user.id, email);
+                }
+                return users::find(conn, user.id).ok_or(IdentityError::EmailTaken);
+            }
+            Err(users::UserCreationError::DuplicatedUsername) => {
+                username = format!("{}-{}", base, thread_rng().gen_range(1000, 10000));
+            }
+            Err(users::UserCreationError::DuplicatedEmail) => {
+                return Err(IdentityError::EmailTaken)
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::config;
+use crate::schema::login_failures;
+use chrono::{DateTime, Duration, Utc};
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error;
+use std::net::IpAddr;
+
+/// Failures are counted per account, whether it exists or not.
+pub fn account_key(email: &str) -> String {
+    format!("email:{}", email.trim().to_lowercase())
+}
+
+pub fn address_key(addr: &IpAddr) -> String {
+    format!("ip:{}", addr)
+}
+
+/// Lockout after `failures` failed logins: none below `max_failures`, then
+/// `LOGIN_LOCK_SECONDS` doubled with every further failure.
+fn lock_seconds(failures: i32, max_failures: i32) -> Option<i64> {
+    if failures < max_failures {
+        return None;
+    }
+    let doublings = (failures - max_failures).min(20) as u32;
+    Some((config::LOGIN_LOCK_SECONDS << doublings).min(config::LOGIN_LOCK_MAX_SECONDS))
+}
+
+/// Seconds until the last lock on these keys ends, None when a login may be tried.
+pub fn retry_after(conn: &PgConnection, keys: &[String]) -> Option<i64> {
+    let now = Utc::now();
+    login_failures::table
+        .filter(login_failures::key.eq_any(keys))
+        .filter(login_failures::locked_until.gt(now))
+        .select(login_failures::locked_until)
+        .load::<Option<DateTime<Utc>>>(conn)
+        .expect("Error loading login failures")
+        .into_iter()
+        .flatten()
+        .map(|until| (until - now).num_seconds() + 1)
+        .max()
+}
+
+pub fn record_failure(conn: &PgConnection, key: &str, max_failures: i32) {
+    let now = Utc::now();
+    conn.transaction::<_, Error, _>(|| {
+        let previous = login_failures::table
+            .find(key)
+            .select((login_failures::failures, login_failures::last_failed_at))
+            .for_update()
+            .get_result::<(i32, DateTime<Utc>)>(conn)
+            .optional()?;
+        let failures = match previous {
+            Some((failures, last_failed_at))
+                if last_failed_at > now - Duration::hours(config::LOGIN_FAILURES_RESET_HOURS) =>
+            {
+                failures + 1
+            }
+            _ => 1,
+        };
+        let locked_until = lock_seconds(failures, max_failures).map(|s| now + Duration::seconds(s));
+        if locked_until.is_some() {
+            log::warn!("login locked for {} after {} failures", key, failures);
+        }
+
+        diesel::insert_into(login_failures::table)
+            .values((
+                login_failures::key.eq(key),
+                login_failures::failures.eq(failures),
+                login_failures::last_failed_at.eq(now),
+                login_failures::locked_until.eq(locked_until),
+            ))
+            .on_conflict(login_failures::key)
+            .do_update()
+            .set((
+                login_failures::failures.eq(failures),
+                login_failures::last_failed_at.eq(now),
+                login_failures::locked_until.eq(locked_until),
+            ))
+            .execute(conn)
+    })
+    .map_err(|err| eprintln!("login_failures::record_failure: {}", err))
+    .ok();
+}
+
+/// Forget the failures of a key, after a good login or to unlock an account by hand.
+pub fn clear(conn: &PgConnection, key: &str) -> bool {
+    diesel::delete(login_failures::table.find(key))
+        .execute(conn)
+        .map(|count| count > 0)
+        .expect("Error clearing login failures")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_seconds() {
+        assert_eq!(lock_seconds(4, 5), None);
+        assert_eq!(lock_seconds(5, 5), Some(config::LOGIN_LOCK_SECONDS));
+        assert_eq!(lock_seconds(7, 5), Some(config::LOGIN_LOCK_SECONDS * 4));
+        assert_eq!(lock_seconds(1000, 5), Some(config::LOGIN_LOCK_MAX_SECONDS));
+    }
+}
@@ -1,18 +1,36 @@
 use crate::config;
+use crate::password;
+use chrono::{DateTime, SecondsFormat, Utc};
 use diesel::PgConnection;
 use dotenv::dotenv;
 use lazy_static::lazy_static;
 use pretty_env_logger;
 use r2d2;
 use r2d2_diesel::ConnectionManager;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sha2::{Digest, Sha256};
 use std::env;
 use std::sync::Mutex;
 // use std::str::FromStr;
 
+pub mod access_tokens;
 pub mod articles;
+pub mod audit;
+pub mod authors;
+pub mod bookmarks;
 pub mod comments;
+pub mod identities;
+pub mod login_failures;
+pub mod password_resets;
 pub mod profiles;
+pub mod reactions;
+pub mod revisions;
+pub mod search;
+pub mod sessions;
+pub mod tags;
+pub mod two_factor;
 pub mod users;
+pub mod views;
 
 #[derive(Debug)]
 pub struct Config {
@@ -21,7 +39,43 @@ pub struct Config {
     // pub port: u16,
     pub database_url: String,
     pub secret_key: String,
+    /// json file listing the asymmetric JWT keys, see `keys`
+    pub jwt_keyring: Option<String>,
+    /// allow running with the built-in secret key
+    pub dev_mode: bool,
     pub public_board: bool,
+    /// base url of the site, for links sent by mail
+    pub public_url: String,
+    /// block writing articles, comments and favorites until the email is verified
+    pub require_verified: bool,
+    /// logins also set HttpOnly cookies, for the bundled front-end
+    pub cookie_sessions: bool,
+    /// cookies are only sent over https, on when `public_url` is https
+    pub cookie_secure: bool,
+    pub mail_transport: String,
+    pub mail_from: String,
+    pub mail_dir: String,
+    pub smtp_url: Option<String>,
+    /// failed logins of an account before it is locked
+    pub login_max_failures: i32,
+    /// failed logins from one address before it is locked
+    pub login_max_failures_per_ip: i32,
+    /// OpenID Connect login is on when the issuer is set
+    pub oidc_issuer: Option<String>,
+    pub oidc_client_id: String,
+    pub oidc_client_secret: String,
+    pub oidc_redirect_url: String,
+    /// how new passwords are hashed, older hashes are upgraded on login
+    pub password_policy: password::Policy,
+    pub password_min_length: usize,
+    /// file of passwords that can't be chosen, one per line
+    pub breached_passwords: Option<String>,
+    /// text search configuration of postgres, e.g. `english` or `simple`
+    pub search_language: String,
+    /// an article keeps its slug when the title is edited
+    pub keep_slugs: bool,
+    /// emoji users can react with, in the order they are shown
+    pub reactions: Vec<String>,
     // pub log_level: String,
 }
 impl Config {
@@ -37,6 +91,57 @@ impl Config {
         let secret_key =
             env::var("SECRET_KEY").unwrap_or_else(|_| config::SECRET.to_string().clone());
 
+        let public_url = env::var("PUBLIC_URL").unwrap_or_else(|_| format!("http://{}", web_url));
+        let require_verified =
+            env::var("REQUIRE_VERIFIED").unwrap_or_else(|_| String::from("false")) == "true";
+        let cookie_sessions =
+            env::var("COOKIE_SESSIONS").unwrap_or_else(|_| String::from("false")) == "true";
+        let cookie_secure = public_url.starts_with("https://");
+        let mail_transport = env::var("MAIL_TRANSPORT").unwrap_or_else(|_| String::from("file"));
+        let mail_from =
+            env::var("MAIL_FROM").unwrap_or_else(|_| String::from("realworld@localhost"));
+        let mail_dir = env::var("MAIL_DIR").unwrap_or_else(|_| String::from("./temp/"));
+        let smtp_url = env::var("SMTP_URL").ok();
+        let login_max_failures = env::var("LOGIN_MAX_FAILURES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(5);
+        let login_max_failures_per_ip = env::var("LOGIN_MAX_FAILURES_PER_IP")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(20);
+        let oidc_issuer = env::var("OIDC_ISSUER").ok();
+        let oidc_client_id = env::var("OIDC_CLIENT_ID").unwrap_or_default();
+        let oidc_client_secret = env::var("OIDC_CLIENT_SECRET").unwrap_or_default();
+        let oidc_redirect_url = env::var("OIDC_REDIRECT_URL")
+            .unwrap_or_else(|_| format!("{}/api/users/oidc/callback", public_url));
+
+        let password_policy = password::Policy {
+            scheme: env::var("PASSWORD_HASH")
+                .ok()
+                .map(|s| s.parse().expect("PASSWORD_HASH must be argon2id or bcrypt"))
+                .unwrap_or(password::Scheme::Argon2id),
+            argon2_memory: env_number("ARGON2_MEMORY_KIB", 19456),
+            argon2_iterations: env_number("ARGON2_ITERATIONS", 2),
+            argon2_parallelism: env_number("ARGON2_PARALLELISM", 1),
+            bcrypt_cost: env_number("BCRYPT_COST", bcrypt::DEFAULT_COST),
+        };
+        let password_min_length = env_number("PASSWORD_MIN_LENGTH", 8);
+        let breached_passwords = env::var("BREACHED_PASSWORDS_FILE").ok();
+        let search_language =
+            env::var("SEARCH_LANGUAGE").unwrap_or_else(|_| String::from("english"));
+
+        let keep_slugs = env::var("KEEP_SLUGS").unwrap_or_else(|_| String::from("false")) == "true";
+        let reactions = env::var("REACTIONS")
+            .unwrap_or_else(|_| config::DEFAULT_REACTIONS.to_string())
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        let jwt_keyring = env::var("JWT_KEYRING").ok();
+        let dev_mode = env::var("DEV_MODE").unwrap_or_else(|_| String::from("false")) == "true";
+
         let database_url =
             env::var("DATABASE_URL").expect("No DATABASE_URL environment variable found");
 
@@ -45,12 +150,41 @@ impl Config {
             web_url,
             database_url,
             secret_key,
+            jwt_keyring,
+            dev_mode,
             public_board,
+            public_url,
+            require_verified,
+            cookie_sessions,
+            cookie_secure,
+            mail_transport,
+            mail_from,
+            mail_dir,
+            smtp_url,
+            login_max_failures,
+            login_max_failures_per_ip,
+            oidc_issuer,
+            oidc_client_id,
+            oidc_client_secret,
+            oidc_redirect_url,
+            password_policy,
+            password_min_length,
+            breached_passwords,
+            search_language,
+            keep_slugs,
+            reactions,
             // log_level,
         }
     }
 }
 
+fn env_number<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(default)
+}
+
 fn pg_poll(url: &String, pool_size: u32) -> r2d2::Pool<ConnectionManager<PgConnection>> {
     let manager = ConnectionManager::<PgConnection>::new(url);
 
@@ -66,6 +200,45 @@ lazy_static! {
     pub static ref UPLOAD_LIST: Mutex<Vec<String>> = Mutex::new(Vec::new());
 }
 
+const SECRET_LEN: usize = 48;
+
+/// Random part of the opaque tokens (refresh, password reset) we hand out.
+pub fn generate_secret() -> String {
+    let mut rng = thread_rng();
+    (0..SECRET_LEN).map(|_| rng.sample(Alphanumeric)).collect()
+}
+
+/// Only the sha256 of a secret is kept in the database.
+pub fn hash_secret(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+/// Compare secrets or their hashes in a time that doesn't tell where they differ.
+pub fn secrets_equal(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+/// Opaque tokens look like `<row id>.<secret>`.
+pub fn parse_token(token: &str) -> Option<(i32, &str)> {
+    let mut parts = token.splitn(2, '.');
+    let id = parts.next()?.parse::<i32>().ok()?;
+    let secret = parts.next()?;
+    Some((id, secret))
+}
+
+/// Why a write to someone's row was refused.
+#[derive(Debug, PartialEq)]
+pub enum WriteError {
+    NotFound,
+    Forbidden,
+    /// a concurrent write took what this one needed
+    Conflict,
+}
+
 // #[database("diesel_postgres_pool")]
 // pub struct Conn(diesel::PgConnection);
 
@@ -73,7 +246,7 @@ use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_builder::*;
 use diesel::query_dsl::methods::LoadQuery;
-use diesel::sql_types::BigInt;
+use diesel::sql_types::{BigInt, Bool};
 
 pub trait OffsetLimit: Sized {
     fn offset_and_limit(self, offset: i64, limit: i64) -> OffsetLimited<Self>;
@@ -128,3 +301,252 @@ where
         Ok(())
     }
 }
+
+/// Where a page of a list starts: the `(timestamp, id)` of the row before it,
+/// handed to clients base64 encoded. Drafts have no timestamp.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub at: Option<DateTime<Utc>>,
+    pub id: i32,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        let at = self
+            .at
+            .map(|at| at.to_rfc3339_opts(SecondsFormat::Micros, true))
+            .unwrap_or_default();
+        base64::encode_config(format!("{}|{}", at, self.id), base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Cursor> {
+        let cursor = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).ok()?;
+        let cursor = String::from_utf8(cursor).ok()?;
+        let mut parts = cursor.splitn(2, '|');
+        let at = match parts.next()? {
+            "" => None,
+            at => Some(DateTime::parse_from_rfc3339(at).ok()?.with_timezone(&Utc)),
+        };
+        let id = parts.next()?.parse().ok()?;
+        Some(Cursor { at, id })
+    }
+}
+
+/// How a list is paged. `offset` keeps working, but it gets slower the
+/// deeper it goes and skips or repeats rows when new ones come in; the
+/// cursors of `after` and `before` don't.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Paging {
+    Offset(i64),
+    After(Cursor),
+    Before(Cursor),
+}
+
+impl Paging {
+    /// A cursor wins over `offset`, None when one is invalid or both of them are given.
+    pub fn new(offset: Option<i64>, after: Option<&str>, before: Option<&str>) -> Option<Paging> {
+        match (after, before) {
+            (Some(_), Some(_)) => None,
+            (Some(after), None) => Cursor::decode(after).map(Paging::After),
+            (None, Some(before)) => Cursor::decode(before).map(Paging::Before),
+            (None, None) => Some(Paging::Offset(offset.unwrap_or(0))),
+        }
+    }
+
+    /// The rows of a page after or before the cursor, in a list ordered by
+    /// `ts DESC, id DESC`. Postgres puts a NULL `ts` first there.
+    fn keyset_sql(&self, ts: &str, id: &str) -> Option<String> {
+        let at = |at: &DateTime<Utc>| {
+            format!(
+                "'{}'::timestamptz",
+                at.to_rfc3339_opts(SecondsFormat::Micros, true)
+            )
+        };
+        let sql = match self {
+            Paging::Offset(_) => return None,
+            Paging::After(Cursor { at: Some(t), id: i }) => format!(
+                "({ts} < {t} OR ({ts} = {t} AND {id} < {i}))",
+                ts = ts,
+                t = at(t),
+                id = id,
+                i = i
+            ),
+            Paging::After(Cursor { at: None, id: i }) => {
+                format!("({} IS NOT NULL OR {} < {})", ts, id, i)
+            }
+            Paging::Before(Cursor { at: Some(t), id: i }) => format!(
+                "({ts} IS NULL OR {ts} > {t} OR ({ts} = {t} AND {id} > {i}))",
+                ts = ts,
+                t = at(t),
+                id = id,
+                i = i
+            ),
+            Paging::Before(Cursor { at: None, id: i }) => {
+                format!("({} IS NULL AND {} > {})", ts, id, i)
+            }
+        };
+        Some(sql)
+    }
+
+    /// `keyset_sql` as a filter, the cursor only holds a timestamp and an id.
+    pub fn keyset(&self, ts: &str, id: &str) -> Option<diesel::expression::SqlLiteral<Bool>> {
+        self.keyset_sql(ts, id).map(|sql| diesel::dsl::sql(&sql))
+    }
+
+    /// Rows before the cursor are loaded the other way round, `ts ASC, id ASC`.
+    pub fn is_reversed(&self) -> bool {
+        matches!(self, Paging::Before(_))
+    }
+
+    /// The page of `rows`. With a cursor they are loaded with one more than
+    /// `limit`, to see if there is a page after; `count` only comes with `offset`.
+    pub fn page<T>(
+        &self,
+        limit: i64,
+        mut rows: Vec<T>,
+        count: Option<i64>,
+        key: impl Fn(&T) -> Cursor,
+    ) -> Page<T> {
+        let limit = limit.max(0) as usize;
+        let (more_after, more_before) = match self {
+            Paging::Offset(offset) => (
+                count
+                    .filter(|&count| offset + (rows.len() as i64) < count)
+                    .is_some(),
+                *offset > 0,
+            ),
+            Paging::After(_) => {
+                let more = rows.len() > limit;
+                rows.truncate(limit);
+                (more, true)
+            }
+            Paging::Before(_) => {
+                let more = rows.len() > limit;
+                rows.truncate(limit);
+                rows.reverse();
+                (true, more)
+            }
+        };
+        let next_cursor = rows
+            .last()
+            .filter(|_| more_after)
+            .map(|row| key(row).encode());
+        let prev_cursor = rows
+            .first()
+            .filter(|_| more_before)
+            .map(|row| key(row).encode());
+        Page {
+            items: rows,
+            count,
+            next_cursor,
+            prev_cursor,
+        }
+    }
+}
+
+/// One page of a list and the cursors of the pages around it.
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub count: Option<i64>,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn empty() -> Page<T> {
+        Page {
+            items: vec![],
+            count: Some(0),
+            next_cursor: None,
+            prev_cursor: None,
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            count: self.count,
+            next_cursor: self.next_cursor,
+            prev_cursor: self.prev_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_secrets_equal() {
+        assert!(secrets_equal("abc", "abc"));
+        assert!(!secrets_equal("abc", "abd"));
+        assert!(!secrets_equal("abc", "ab"));
+        assert!(secrets_equal("", ""));
+    }
+
+    #[test]
+    fn test_cursor() {
+        let cursor = Cursor {
+            at: Some(Utc.timestamp_opt(1_700_000_000, 123_456_000).unwrap()),
+            id: 42,
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        let draft = Cursor { at: None, id: 7 };
+        assert_eq!(Cursor::decode(&draft.encode()), Some(draft));
+        assert_eq!(Cursor::decode("not a cursor"), None);
+    }
+
+    #[test]
+    fn test_paging() {
+        let cursor = Cursor { at: None, id: 7 };
+        assert_eq!(Paging::new(Some(20), None, None), Some(Paging::Offset(20)));
+        assert_eq!(
+            Paging::new(Some(20), Some(&cursor.encode()), None),
+            Some(Paging::After(cursor.clone()))
+        );
+        assert_eq!(
+            Paging::new(None, Some(&cursor.encode()), Some(&cursor.encode())),
+            None
+        );
+        assert_eq!(Paging::new(None, None, Some("?")), None);
+        assert_eq!(
+            Paging::After(cursor).keyset_sql("a.ts", "a.id"),
+            Some(String::from("(a.ts IS NOT NULL OR a.id < 7)"))
+        );
+        let cursor = Cursor {
+            at: Some(Utc.timestamp_opt(0, 0).unwrap()),
+            id: 7,
+        };
+        assert_eq!(
+            Paging::Before(cursor).keyset_sql("a.ts", "a.id"),
+            Some(String::from(
+                "(a.ts IS NULL OR a.ts > '1970-01-01T00:00:00.000000Z'::timestamptz \
+                 OR (a.ts = '1970-01-01T00:00:00.000000Z'::timestamptz AND a.id > 7))"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_page() {
+        let key = |id: &i32| Cursor { at: None, id: *id };
+        let after = Paging::After(key(&10));
+        let page = after.page(2, vec![9, 8, 7], None, key);
+        assert_eq!(page.items, vec![9, 8]);
+        assert_eq!(page.next_cursor, Some(key(&8).encode()));
+        assert_eq!(page.prev_cursor, Some(key(&9).encode()));
+        let before = Paging::Before(key(&7));
+        let page = before.page(2, vec![8, 9], None, key);
+        assert_eq!(page.items, vec![9, 8]);
+        assert_eq!(page.prev_cursor, None);
+        let page = Paging::Offset(0).page(2, vec![9, 8], Some(2), key);
+        assert_eq!((page.next_cursor, page.prev_cursor), (None, None));
+    }
+
+    #[test]
+    fn test_parse_token() {
+        assert_eq!(parse_token("12.abc.def"), Some((12, "abc.def")));
+        assert_eq!(parse_token("abc"), None);
+        assert_eq!(parse_token("12"), None);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::config;
+use crate::db::{
+    generate_secret, hash_secret, login_failures, parse_token, secrets_equal, sessions,
+};
+use crate::models::user::User;
+use crate::password;
+use crate::schema::{password_resets, users};
+use chrono::{Duration, Utc};
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error;
+
+/// Issue a reset token for the account with this email. None when there is
+/// no such account or it asked too often, the caller must not tell which.
+pub fn request(conn: &PgConnection, email: &str) -> Option<(User, String)> {
+    let user = users::table
+        .filter(users::email.eq(email))
+        .get_result::<User>(conn)
+        .ok()?;
+
+    let now = Utc::now();
+    let recent = password_resets::table
+        .filter(password_resets::user.eq(user.id))
+        .filter(password_resets::created_at.gt(now - Duration::hours(1)))
+        .count()
+        .get_result::<i64>(conn)
+        .expect("Error counting password resets");
+    if recent >= config::RESET_REQUESTS_PER_HOUR {
+        log::warn!("too many password resets requested for user {}", user.id);
+        return None;
+    }
+
+    let secret = generate_secret();
+    conn.transaction::<_, Error, _>(|| {
+        // only the newest link works
+        diesel::update(
+            password_resets::table
+                .filter(password_resets::user.eq(user.id))
+                .filter(password_resets::used_at.is_null()),
+        )
+        .set(password_resets::used_at.eq(now))
+        .execute(conn)?;
+
+        diesel::insert_into(password_resets::table)
+            .values((
+                password_resets::user.eq(user.id),
+                password_resets::token_hash.eq(hash_secret(&secret)),
+                password_resets::expires_at
+                    .eq(now + Duration::minutes(config::RESET_EXPIRE_MINUTES)),
+            ))
+            .returning(password_resets::id)
+            .get_result::<i32>(conn)
+    })
+    .map_err(|err| eprintln!("password_resets::request: {}", err))
+    .ok()
+    .map(|id| (user, format!("{}.{}", id, secret)))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ResetError {
+    Invalid,
+    /// seconds until the account may try again
+    Locked(i64),
+}
+
+/// Wrong tokens are counted per account, like failed logins but apart from them.
+fn account_key(email: &str) -> String {
+    format!("reset:{}", email.trim().to_lowercase())
+}
+
+/// Set a new password with a reset token. A token works once. Wrong guesses
+/// leave the pending token alone and lock the resets of the account after
+/// `RESET_MAX_ATTEMPTS`. All sessions of the user are revoked.
+pub fn reset(conn: &PgConnection, token: &str, password: &str) -> Result<User, ResetError> {
+    let (id, secret) = parse_token(token).ok_or(ResetError::Invalid)?;
+    let now = Utc::now();
+    let (user_id, email, token_hash) = password_resets::table
+        .inner_join(users::table)
+        .filter(password_resets::id.eq(id))
+        .filter(password_resets::used_at.is_null())
+        .filter(password_resets::expires_at.gt(now))
+        .select((users::id, users::email, password_resets::token_hash))
+        .get_result::<(i32, String, String)>(conn)
+        .map_err(|_| ResetError::Invalid)?;
+
+    let key = account_key(&email);
+    if let Some(seconds) = login_failures::retry_after(conn, std::slice::from_ref(&key)) {
+        return Err(ResetError::Locked(seconds));
+    }
+    if !secrets_equal(&token_hash, &hash_secret(secret)) {
+        log::warn!("wrong password reset token for user {}", user_id);
+        login_failures::record_failure(conn, &key, config::RESET_MAX_ATTEMPTS);
+        return Err(ResetError::Invalid);
+    }
+    login_failures::clear(conn, &key);
+
+    let shash = password::hash(password);
+    conn.transaction::<_, Error, _>(|| {
+        // a concurrent reset with the same token may have won since the check
+        let used = diesel::update(
+            password_resets::table
+                .find(id)
+                .filter(password_resets::used_at.is_null())
+                .filter(password_resets::expires_at.gt(now)),
+        )
+        .set(password_resets::used_at.eq(now))
+        .execute(conn)?;
+        if used != 1 {
+            return Err(Error::RollbackTransaction);
+        }
+        let user = diesel::update(users::table.find(user_id))
+            .set(users::hash.eq(shash))
+            .get_result::<User>(conn)?;
+        sessions::revoke_all(conn, user_id)?;
+        Ok(user)
+    })
+    .map_err(|err| {
+        if !matches!(err, Error::RollbackTransaction) {
+            eprintln!("password_resets::reset: {}", err);
+        }
+        ResetError::Invalid
+    })
+}
@@ -0,0 +1,206 @@
+//! Emoji reactions to articles and comments, one of each emoji per user.
+//! Only the emoji of `REACTIONS` can be added and are counted, in its order.
+use crate::db::CONFIG;
+use crate::models::article::ArticleJson;
+use crate::models::comment::CommentJson;
+use crate::models::reaction::Reaction;
+use crate::schema::article_reactions;
+use crate::schema::comment_reactions;
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Array, BigInt, Bool, Int4, Text};
+use std::collections::HashMap;
+
+#[derive(QueryableByName, Debug)]
+struct ReactionCount {
+    /// the article or comment
+    #[sql_type = "Int4"]
+    target: i32,
+    #[sql_type = "Text"]
+    emoji: String,
+    #[sql_type = "BigInt"]
+    count: i64,
+    #[sql_type = "Bool"]
+    reacted: bool,
+}
+
+pub fn is_allowed(emoji: &str) -> bool {
+    CONFIG.reactions.iter().any(|allowed| allowed == emoji)
+}
+
+/// The counts by target, each in the order of `allowed` and without the
+/// emoji taken out of it since.
+fn group(counts: Vec<ReactionCount>, allowed: &[String]) -> HashMap<i32, Vec<Reaction>> {
+    let mut grouped: HashMap<i32, Vec<(usize, Reaction)>> = HashMap::new();
+    for count in counts {
+        if let Some(position) = allowed.iter().position(|emoji| *emoji == count.emoji) {
+            grouped.entry(count.target).or_default().push((
+                position,
+                Reaction {
+                    emoji: count.emoji,
+                    count: count.count,
+                    reacted: count.reacted,
+                },
+            ));
+        }
+    }
+    grouped
+        .into_iter()
+        .map(|(target, mut reactions)| {
+            reactions.sort_by_key(|(position, _)| *position);
+            (target, reactions.into_iter().map(|(_, r)| r).collect())
+        })
+        .collect()
+}
+
+/// The reactions to the articles `ids`, `reacted` as seen by `user_id`.
+pub fn of_articles(
+    conn: &PgConnection,
+    ids: &[i32],
+    user_id: Option<i32>,
+) -> HashMap<i32, Vec<Reaction>> {
+    if ids.is_empty() {
+        return HashMap::new();
+    }
+    let counts = sql_query(
+        "SELECT article AS target, emoji, COUNT(*) AS count, BOOL_OR(\"user\" = $2) AS reacted \
+         FROM article_reactions WHERE article = ANY($1) GROUP BY article, emoji",
+    )
+    .bind::<Array<Int4>, _>(ids)
+    .bind::<Int4, _>(user_id.unwrap_or(0))
+    .load(conn)
+    .expect("Cannot load article reactions");
+    group(counts, &CONFIG.reactions)
+}
+
+/// The reactions to the comments `ids`, `reacted` as seen by `user_id`.
+pub fn of_comments(
+    conn: &PgConnection,
+    ids: &[i32],
+    user_id: Option<i32>,
+) -> HashMap<i32, Vec<Reaction>> {
+    if ids.is_empty() {
+        return HashMap::new();
+    }
+    let counts = sql_query(
+        "SELECT comment AS target, emoji, COUNT(*) AS count, BOOL_OR(\"user\" = $2) AS reacted \
+         FROM comment_reactions WHERE comment = ANY($1) GROUP BY comment, emoji",
+    )
+    .bind::<Array<Int4>, _>(ids)
+    .bind::<Int4, _>(user_id.unwrap_or(0))
+    .load(conn)
+    .expect("Cannot load comment reactions");
+    group(counts, &CONFIG.reactions)
+}
+
+/// Fill in the reactions of a list of articles.
+pub fn attach_articles(conn: &PgConnection, articles: &mut [ArticleJson], user_id: Option<i32>) {
+    let ids: Vec<i32> = articles.iter().map(|article| article.id).collect();
+    let mut reactions = of_articles(conn, &ids, user_id);
+    for article in articles {
+        article.reactions = reactions.remove(&article.id).unwrap_or_default();
+    }
+}
+
+/// Fill in the reactions of a list of comments.
+pub fn attach_comments(conn: &PgConnection, comments: &mut [CommentJson], user_id: Option<i32>) {
+    let ids: Vec<i32> = comments.iter().map(|comment| comment.id).collect();
+    let mut reactions = of_comments(conn, &ids, user_id);
+    for comment in comments {
+        comment.reactions = reactions.remove(&comment.id).unwrap_or_default();
+    }
+}
+
+/// React to an article, again with the same emoji changes nothing.
+pub fn add_to_article(
+    conn: &PgConnection,
+    article_id: i32,
+    user_id: i32,
+    emoji: &str,
+) -> QueryResult<()> {
+    diesel::insert_into(article_reactions::table)
+        .values((
+            article_reactions::article.eq(article_id),
+            article_reactions::user.eq(user_id),
+            article_reactions::emoji.eq(emoji),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(())
+}
+
+pub fn remove_from_article(
+    conn: &PgConnection,
+    article_id: i32,
+    user_id: i32,
+    emoji: &str,
+) -> QueryResult<()> {
+    diesel::delete(article_reactions::table.find((article_id, user_id, emoji))).execute(conn)?;
+    Ok(())
+}
+
+/// React to a comment, again with the same emoji changes nothing.
+pub fn add_to_comment(
+    conn: &PgConnection,
+    comment_id: i32,
+    user_id: i32,
+    emoji: &str,
+) -> QueryResult<()> {
+    diesel::insert_into(comment_reactions::table)
+        .values((
+            comment_reactions::comment.eq(comment_id),
+            comment_reactions::user.eq(user_id),
+            comment_reactions::emoji.eq(emoji),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(())
+}
+
+pub fn remove_from_comment(
+    conn: &PgConnection,
+    comment_id: i32,
+    user_id: i32,
+    emoji: &str,
+) -> QueryResult<()> {
+    diesel::delete(comment_reactions::table.find((comment_id, user_id, emoji))).execute(conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group() {
+        let count = |target, emoji: &str, count, reacted| ReactionCount {
+            target,
+            emoji: emoji.to_string(),
+            count,
+            reacted,
+        };
+        let reaction = |emoji: &str, count, reacted| Reaction {
+            emoji: emoji.to_string(),
+            count,
+            reacted,
+        };
+        let allowed = vec![String::from("👍"), String::from("🎉"), String::from("❤️")];
+        let grouped = group(
+            vec![
+                count(1, "❤️", 1, false),
+                count(1, "👍", 3, true),
+                count(1, "🙈", 2, false),
+                count(2, "🎉", 1, true),
+            ],
+            &allowed,
+        );
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(
+            grouped[&1],
+            vec![reaction("👍", 3, true), reaction("❤️", 1, false)]
+        );
+        assert_eq!(grouped[&2], vec![reaction("🎉", 1, true)]);
+    }
+}
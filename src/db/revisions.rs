@@ -0,0 +1,88 @@
+//! Every version of an article, numbered from 1, with the full content and
+//! who wrote it. They go with the article when it is deleted.
+use crate::diff;
+use crate::models::article::Article;
+use crate::models::revision::{Revision, RevisionJson};
+use crate::schema::article_revisions;
+use crate::schema::articles;
+use crate::schema::users;
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+/// Keep the content `article` has now as its next revision. Call it in the
+/// transaction that wrote the article, the row stays locked until the end so
+/// two edits can't take the same number.
+pub fn record(conn: &PgConnection, article: &Article, editor: i32) -> QueryResult<i32> {
+    articles::table
+        .find(article.id)
+        .select(articles::id)
+        .for_update()
+        .execute(conn)?;
+    let last = article_revisions::table
+        .select(article_revisions::number)
+        .filter(article_revisions::article.eq(article.id))
+        .order(article_revisions::number.desc())
+        .first::<i32>(conn)
+        .optional()?;
+    diesel::insert_into(article_revisions::table)
+        .values((
+            article_revisions::article.eq(article.id),
+            article_revisions::number.eq(last.unwrap_or(0) + 1),
+            article_revisions::editor.eq(editor),
+            article_revisions::title.eq(&article.title),
+            article_revisions::description.eq(&article.description),
+            article_revisions::body.eq(&article.body),
+            article_revisions::tag_list.eq(&article.tag_list),
+        ))
+        .returning(article_revisions::number)
+        .get_result(conn)
+}
+
+/// The revisions of an article without their bodies, the newest first.
+pub fn list(conn: &PgConnection, article_id: i32) -> Vec<RevisionJson> {
+    article_revisions::table
+        .left_join(users::table)
+        .select((article_revisions::all_columns, users::username.nullable()))
+        .filter(article_revisions::article.eq(article_id))
+        .order(article_revisions::number.desc())
+        .load::<(Revision, Option<String>)>(conn)
+        .expect("Cannot load revisions")
+        .into_iter()
+        .map(|(revision, editor)| revision.summary(editor))
+        .collect()
+}
+
+/// Revision `number` of an article and the username of its editor.
+pub fn find(
+    conn: &PgConnection,
+    article_id: i32,
+    number: i32,
+) -> Option<(Revision, Option<String>)> {
+    article_revisions::table
+        .left_join(users::table)
+        .select((article_revisions::all_columns, users::username.nullable()))
+        .filter(article_revisions::article.eq(article_id))
+        .filter(article_revisions::number.eq(number))
+        .get_result(conn)
+        .optional()
+        .expect("Cannot load revision")
+}
+
+/// The unified diff between revisions `from` and `to` of an article.
+pub fn diff(
+    conn: &PgConnection,
+    slug: &str,
+    article_id: i32,
+    from: i32,
+    to: i32,
+) -> Option<String> {
+    let (old, _) = find(conn, article_id, from)?;
+    let (new, _) = find(conn, article_id, to)?;
+    Some(diff::unified(
+        &old.text(),
+        &new.text(),
+        &format!("{}@{}", slug, from),
+        &format!("{}@{}", slug, to),
+    ))
+}
@@ -0,0 +1,324 @@
+//! Full-text search over articles, comments and user profiles.
+//!
+//! Each table has a `search` tsvector column kept up to date by triggers, see
+//! the `add_search` migration. Diesel has no type for it, so the columns are
+//! left out of `schema.rs` and only show up in the SQL fragments here.
+use crate::db::authors;
+use crate::db::bookmarks;
+use crate::db::reactions;
+use crate::db::OffsetLimit;
+use crate::models::article::{Article, ArticleJson, Status};
+use crate::models::comment::{Comment, CommentJson};
+use crate::models::user::{Profile, User};
+use crate::schema::{articles, comments, favorites, follows, users};
+use diesel;
+use diesel::dsl::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Bool, Float, Text};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LIMIT: i64 = 20;
+
+// ts_headline marks the matches with these, they become <mark> after escaping
+const START_SEL: char = '\u{2}';
+const STOP_SEL: char = '\u{3}';
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    Articles,
+    Comments,
+    Users,
+}
+
+impl std::str::FromStr for Scope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "articles" => Ok(Scope::Articles),
+            "comments" => Ok(Scope::Comments),
+            "users" => Ok(Scope::Users),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct Search {
+    pub q: Option<String>,
+    /// comma separated `articles`, `comments` and `users`, only articles if not given
+    pub scope: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl Search {
+    /// None when one of the scopes is unknown.
+    pub fn scopes(&self) -> Option<Vec<Scope>> {
+        match &self.scope {
+            Some(scope) => scope.split(',').map(|s| s.trim().parse().ok()).collect(),
+            None => Some(vec![Scope::Articles]),
+        }
+    }
+}
+
+/// A result with how well it matches and where.
+#[derive(Serialize)]
+pub struct Hit<T> {
+    #[serde(flatten)]
+    pub item: T,
+    pub rank: f32,
+    /// html escaped text around the matches, which are in `<mark>`
+    pub snippet: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentHit {
+    #[serde(flatten)]
+    pub comment: CommentJson,
+    pub article_slug: String,
+}
+
+/// Escape a ts_headline for html and turn its selection marks into `<mark>`.
+fn highlight(headline: &str) -> String {
+    let mut snippet = String::with_capacity(headline.len());
+    for c in headline.chars() {
+        match c {
+            START_SEL => snippet.push_str("<mark>"),
+            STOP_SEL => snippet.push_str("</mark>"),
+            '&' => snippet.push_str("&amp;"),
+            '<' => snippet.push_str("&lt;"),
+            '>' => snippet.push_str("&gt;"),
+            '"' => snippet.push_str("&quot;"),
+            '\'' => snippet.push_str("&#39;"),
+            c => snippet.push(c),
+        }
+    }
+    snippet
+}
+
+/// `$before websearch_to_tsquery(q) $after` with `q` bound
+macro_rules! tsquery {
+    ($st:ty, $before:expr, $q:expr, $after:expr) => {
+        sql::<$st>(concat!($before, "websearch_to_tsquery(search_language(), "))
+            .bind::<Text, _>($q)
+            .sql(concat!(")", $after))
+    };
+}
+
+macro_rules! headline {
+    ($document:expr, $q:expr) => {
+        tsquery!(
+            Text,
+            concat!("ts_headline(search_language(), ", $document, ", "),
+            $q,
+            ", 'StartSel=\"\u{2}\", StopSel=\"\u{3}\", MaxWords=35, MinWords=15, MaxFragments=2')"
+        )
+    };
+}
+
+/// Articles matching `q`, the best first; a title match ranks over the body.
+pub fn articles(
+    conn: &PgConnection,
+    q: &str,
+    params: &Search,
+    user_id: Option<i32>,
+) -> (Vec<Hit<ArticleJson>>, i64) {
+    let rank = || tsquery!(Float, "ts_rank(articles.search, ", q, ")");
+    let (mut hits, count): (Vec<Hit<ArticleJson>>, i64) = articles::table
+        .inner_join(users::table)
+        .left_join(
+            favorites::table.on(articles::id
+                .eq(favorites::article)
+                .and(favorites::user.eq(user_id.unwrap_or(0)))),
+        )
+        .select((
+            articles::all_columns,
+            users::all_columns,
+            favorites::user.nullable().is_not_null(),
+            rank(),
+            headline!("articles.description || ' ' || articles.body", q),
+        ))
+        .filter(tsquery!(Bool, "articles.search @@ ", q, ""))
+        .filter(
+            articles::status
+                .eq(Status::Published.as_str())
+                .or(articles::author.eq(user_id.unwrap_or(0)))
+                .or(articles::id.eq_any(authors::co_author_of(user_id.unwrap_or(0)))),
+        )
+        .order((rank().desc(), articles::id.desc()))
+        .offset_and_limit(
+            params.offset.unwrap_or(0),
+            params.limit.unwrap_or(DEFAULT_LIMIT),
+        )
+        .load_and_count::<(Article, User, bool, f32, String)>(conn)
+        .map(|(res, count)| {
+            (
+                res.into_iter()
+                    .map(|(article, author, favorited, rank, headline)| Hit {
+                        item: article.attach(author, favorited),
+                        rank,
+                        snippet: highlight(&headline),
+                    })
+                    .collect(),
+                count,
+            )
+        })
+        .expect("Cannot search articles");
+    let ids: Vec<i32> = hits.iter().map(|hit| hit.item.id).collect();
+    let mut authors = authors::of_articles(conn, &ids, user_id);
+    let mut reactions = reactions::of_articles(conn, &ids, user_id);
+    let bookmarked = user_id.map(|user_id| bookmarks::bookmarked(conn, user_id, &ids));
+    for hit in &mut hits {
+        hit.item.authors = authors.remove(&hit.item.id).unwrap_or_default();
+        hit.item.reactions = reactions.remove(&hit.item.id).unwrap_or_default();
+        hit.item.bookmarked = bookmarked.as_ref().map(|ids| ids.contains(&hit.item.id));
+    }
+    (hits, count)
+}
+
+/// Comments of the articles `user_id` can see.
+pub fn comments(
+    conn: &PgConnection,
+    q: &str,
+    params: &Search,
+    user_id: Option<i32>,
+) -> (Vec<Hit<CommentHit>>, i64) {
+    let rank = || tsquery!(Float, "ts_rank(comments.search, ", q, ")");
+    let (mut hits, count): (Vec<Hit<CommentHit>>, i64) = comments::table
+        .inner_join(articles::table)
+        .inner_join(users::table)
+        .select((
+            comments::all_columns,
+            users::all_columns,
+            articles::slug,
+            rank(),
+            headline!("comments.body", q),
+        ))
+        .filter(tsquery!(Bool, "comments.search @@ ", q, ""))
+        .filter(
+            articles::status
+                .eq(Status::Published.as_str())
+                .or(articles::author.eq(user_id.unwrap_or(0)))
+                .or(articles::id.eq_any(authors::co_author_of(user_id.unwrap_or(0)))),
+        )
+        .order((rank().desc(), comments::id.desc()))
+        .offset_and_limit(
+            params.offset.unwrap_or(0),
+            params.limit.unwrap_or(DEFAULT_LIMIT),
+        )
+        .load_and_count::<(Comment, User, String, f32, String)>(conn)
+        .map(|(res, count)| {
+            (
+                res.into_iter()
+                    .map(|(comment, author, article_slug, rank, headline)| Hit {
+                        item: CommentHit {
+                            comment: comment.attach(author),
+                            article_slug,
+                        },
+                        rank,
+                        snippet: highlight(&headline),
+                    })
+                    .collect(),
+                count,
+            )
+        })
+        .expect("Cannot search comments");
+    let ids: Vec<i32> = hits.iter().map(|hit| hit.item.comment.id).collect();
+    let mut reactions = reactions::of_comments(conn, &ids, user_id);
+    for hit in &mut hits {
+        hit.item.comment.reactions = reactions.remove(&hit.item.comment.id).unwrap_or_default();
+    }
+    (hits, count)
+}
+
+/// Profiles by username or bio, `following` as seen by `user_id`.
+pub fn users(
+    conn: &PgConnection,
+    q: &str,
+    params: &Search,
+    user_id: Option<i32>,
+) -> (Vec<Hit<Profile>>, i64) {
+    let rank = || tsquery!(Float, "ts_rank(users.search, ", q, ")");
+    users::table
+        .left_join(
+            follows::table.on(users::id
+                .eq(follows::followed)
+                .and(follows::follower.eq(user_id.unwrap_or(0)))),
+        )
+        .select((
+            users::all_columns,
+            follows::follower.nullable().is_not_null(),
+            rank(),
+            headline!("users.username || ' ' || coalesce(users.bio, '')", q),
+        ))
+        .filter(tsquery!(Bool, "users.search @@ ", q, ""))
+        .order((rank().desc(), users::id.desc()))
+        .offset_and_limit(
+            params.offset.unwrap_or(0),
+            params.limit.unwrap_or(DEFAULT_LIMIT),
+        )
+        .load_and_count::<(User, bool, f32, String)>(conn)
+        .map(|(res, count)| {
+            (
+                res.into_iter()
+                    .map(|(user, following, rank, headline)| Hit {
+                        item: user.to_profile(following),
+                        rank,
+                        snippet: highlight(&headline),
+                    })
+                    .collect(),
+                count,
+            )
+        })
+        .expect("Cannot search users")
+}
+
+/// Switch the text search configuration, e.g. `english` or `simple`, and
+/// rebuild every search column when it changed. True if it did.
+pub fn set_language(conn: &PgConnection, language: &str) -> QueryResult<bool> {
+    conn.transaction(|| {
+        let changed = sql_query(
+            "UPDATE search_settings SET language = $1::regconfig WHERE language <> $1::regconfig",
+        )
+        .bind::<Text, _>(language)
+        .execute(conn)?
+            > 0;
+        if changed {
+            sql_query("UPDATE articles SET search = NULL").execute(conn)?;
+            sql_query("UPDATE comments SET search = NULL").execute(conn)?;
+            sql_query("UPDATE users SET search = NULL").execute(conn)?;
+        }
+        Ok(changed)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("Train your \u{2}dragon\u{3} <b>now</b> & \"today\""),
+            "Train your <mark>dragon</mark> &lt;b&gt;now&lt;/b&gt; &amp; &quot;today&quot;"
+        );
+    }
+
+    #[test]
+    fn test_scopes() {
+        let search = |scope: Option<&str>| Search {
+            scope: scope.map(String::from),
+            ..Search::default()
+        };
+        assert_eq!(search(None).scopes(), Some(vec![Scope::Articles]));
+        assert_eq!(
+            search(Some("users, comments")).scopes(),
+            Some(vec![Scope::Users, Scope::Comments])
+        );
+        assert_eq!(search(Some("articles,tags")).scopes(), None);
+    }
+}
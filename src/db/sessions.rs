@@ -0,0 +1,184 @@
+use crate::config;
+use crate::db::{generate_secret, hash_secret, parse_token, secrets_equal};
+use crate::models::session::Session;
+use crate::schema::sessions;
+use chrono::{DateTime, Duration, Utc};
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::net::IpAddr;
+
+/// Where a session was opened from, shown in the user's list of sessions.
+#[derive(Debug, Default, Clone)]
+pub struct Device {
+    pub user_agent: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+/// Open a new login session, return it with the refresh token for the client.
+/// The refresh token is `<session id>.<secret>`, only the secret's hash is stored.
+pub fn create(conn: &PgConnection, user_id: i32, device: &Device) -> (Session, String) {
+    let secret = generate_secret();
+    let expires_at = Utc::now() + Duration::days(config::LOGIN_EXPIRE_DAYS);
+
+    let session = diesel::insert_into(sessions::table)
+        .values((
+            sessions::user.eq(user_id),
+            sessions::refresh_hash.eq(hash_secret(&secret)),
+            sessions::expires_at.eq(expires_at),
+            sessions::user_agent.eq(&device.user_agent),
+            sessions::ip.eq(device.ip.map(|ip| ip.to_string())),
+            sessions::csrf_token.eq(generate_secret()),
+        ))
+        .get_result::<Session>(conn)
+        .expect("Error creating session");
+    let token = format!("{}.{}", session.id, secret);
+    (session, token)
+}
+
+/// Exchange a refresh token for a new one. Every refresh token can be used
+/// only once, presenting the token rotated out last revokes the whole session
+/// because somebody else is holding a copy of it. Any other wrong secret only
+/// fails, session ids are easy to guess.
+pub fn refresh(conn: &PgConnection, token: &str) -> Option<(Session, String)> {
+    let (id, secret) = parse_token(token)?;
+    let hash = hash_secret(secret);
+    let now = Utc::now();
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        // locked, two refreshes with the same token can't both rotate it
+        let session = sessions::table
+            .find(id)
+            .filter(sessions::revoked_at.is_null())
+            .filter(sessions::expires_at.gt(now))
+            .for_update()
+            .get_result::<Session>(conn)
+            .optional()?;
+        let session = match session {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+
+        if !secrets_equal(&session.refresh_hash, &hash) {
+            let reused = session
+                .previous_refresh_hash
+                .as_deref()
+                .is_some_and(|previous| secrets_equal(previous, &hash));
+            if reused {
+                log::warn!("refresh token reused for session {}, revoking", session.id);
+                revoke(conn, session.id, session.user);
+            }
+            return Ok(None);
+        }
+
+        let secret = generate_secret();
+        let session = diesel::update(
+            sessions::table
+                .find(session.id)
+                .filter(sessions::revoked_at.is_null()),
+        )
+        .set((
+            sessions::refresh_hash.eq(hash_secret(&secret)),
+            sessions::previous_refresh_hash.eq(&hash),
+            // the same for the whole session, so a refresh can't race a write
+            sessions::csrf_token.eq(session.csrf_token.unwrap_or_else(generate_secret)),
+            sessions::expires_at.eq(now + Duration::days(config::LOGIN_EXPIRE_DAYS)),
+            sessions::last_seen_at.eq(now),
+        ))
+        .get_result::<Session>(conn)
+        .optional()?;
+        Ok(session.map(|session| {
+            let token = format!("{}.{}", session.id, secret);
+            (session, token)
+        }))
+    })
+    .map_err(|err| eprintln!("sessions::refresh: {}", err))
+    .ok()?
+}
+
+/// Whether the session of an access token is still open. Notes when it was
+/// last seen, at most once per `SESSION_SEEN_MINUTES`.
+pub fn touch(conn: &PgConnection, id: i32) -> bool {
+    let now = Utc::now();
+    let last_seen_at = sessions::table
+        .find(id)
+        .filter(sessions::revoked_at.is_null())
+        .filter(sessions::expires_at.gt(now))
+        .select(sessions::last_seen_at)
+        .get_result::<DateTime<Utc>>(conn)
+        .optional()
+        .expect("Error loading session");
+    match last_seen_at {
+        Some(t) => {
+            if t < now - Duration::minutes(config::SESSION_SEEN_MINUTES) {
+                diesel::update(sessions::table.find(id))
+                    .set(sessions::last_seen_at.eq(now))
+                    .execute(conn)
+                    .map_err(|err| eprintln!("sessions::touch: {}", err))
+                    .ok();
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// The CSRF token of an open session.
+pub fn csrf_token(conn: &PgConnection, id: i32) -> Option<String> {
+    sessions::table
+        .find(id)
+        .filter(sessions::revoked_at.is_null())
+        .select(sessions::csrf_token)
+        .get_result::<Option<String>>(conn)
+        .optional()
+        .expect("Error loading session")
+        .flatten()
+}
+
+/// The open sessions of a user, the most recently seen first.
+pub fn list(conn: &PgConnection, user_id: i32) -> Vec<Session> {
+    sessions::table
+        .filter(sessions::user.eq(user_id))
+        .filter(sessions::revoked_at.is_null())
+        .filter(sessions::expires_at.gt(Utc::now()))
+        .order(sessions::last_seen_at.desc())
+        .load(conn)
+        .expect("Cannot load sessions")
+}
+
+pub fn revoke(conn: &PgConnection, id: i32, user_id: i32) -> bool {
+    diesel::update(
+        sessions::table
+            .find(id)
+            .filter(sessions::user.eq(user_id))
+            .filter(sessions::revoked_at.is_null()),
+    )
+    .set(sessions::revoked_at.eq(Utc::now()))
+    .execute(conn)
+    .map(|count| count > 0)
+    .unwrap_or_else(|err| {
+        eprintln!("sessions::revoke: {}", err);
+        false
+    })
+}
+
+pub fn revoke_all(conn: &PgConnection, user_id: i32) -> QueryResult<usize> {
+    diesel::update(
+        sessions::table
+            .filter(sessions::user.eq(user_id))
+            .filter(sessions::revoked_at.is_null()),
+    )
+    .set(sessions::revoked_at.eq(Utc::now()))
+    .execute(conn)
+}
+
+/// Log out everywhere else, only the session `keep` stays open.
+pub fn revoke_others(conn: &PgConnection, user_id: i32, keep: i32) -> QueryResult<usize> {
+    diesel::update(
+        sessions::table
+            .filter(sessions::user.eq(user_id))
+            .filter(sessions::id.ne(keep))
+            .filter(sessions::revoked_at.is_null()),
+    )
+    .set(sessions::revoked_at.eq(Utc::now()))
+    .execute(conn)
+}
@@ -0,0 +1,246 @@
+//! Tags live in `articles.tag_list` as written, and in `tags` with
+//! `article_tags` to count and administrate them. Both are written together.
+use crate::config;
+use crate::models::article::{Article, Status};
+use crate::schema::article_tags;
+use crate::schema::articles;
+use crate::schema::tags;
+use diesel;
+use diesel::dsl::exists;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Bool, Text};
+use serde::Serialize;
+
+/// `  Dragon   Training ` is `dragon training`.
+pub fn normalize(tag: &str) -> String {
+    tag.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// The tags normalized in their order, without empty ones and duplicates.
+pub fn normalize_list(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags.iter().map(|tag| normalize(tag)) {
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// The tags of an article normalized, or why they can't be used.
+pub fn check(conn: &PgConnection, tags: &[String]) -> Result<Vec<String>, &'static str> {
+    let tags = normalize_list(tags);
+    if tags.len() > config::MAX_TAGS {
+        return Err("has too many tags");
+    }
+    if tags
+        .iter()
+        .any(|tag| tag.chars().count() > config::MAX_TAG_LENGTH)
+    {
+        return Err("has a tag that is too long");
+    }
+    if diesel::select(exists(
+        tags::table
+            .filter(tags::name.eq_any(&tags))
+            .filter(tags::blocked),
+    ))
+    .get_result::<bool>(conn)
+    .expect("Error loading tags")
+    {
+        return Err("has a blocked tag");
+    }
+    Ok(tags)
+}
+
+/// The tags normalized and without the blocked ones, for tags from before.
+pub fn allowed(conn: &PgConnection, tags: &[String]) -> Vec<String> {
+    let tags = normalize_list(tags);
+    let blocked = tags::table
+        .select(tags::name)
+        .filter(tags::name.eq_any(&tags))
+        .filter(tags::blocked)
+        .load::<String>(conn)
+        .expect("Error loading tags");
+    tags.into_iter()
+        .filter(|tag| !blocked.contains(tag))
+        .collect()
+}
+
+/// Make `article_tags` of an article what its `tag_list` says, adding new tags.
+pub fn sync(conn: &PgConnection, article: &Article) -> QueryResult<()> {
+    diesel::delete(article_tags::table.filter(article_tags::article.eq(article.id)))
+        .execute(conn)?;
+    if article.tag_list.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<_> = article
+        .tag_list
+        .iter()
+        .map(|name| tags::name.eq(name))
+        .collect();
+    diesel::insert_into(tags::table)
+        .values(&names)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    let ids = tags::table
+        .select(tags::id)
+        .filter(tags::name.eq_any(&article.tag_list))
+        .load::<i32>(conn)?;
+    let rows: Vec<_> = ids
+        .into_iter()
+        .map(|tag| {
+            (
+                article_tags::article.eq(article.id),
+                article_tags::tag.eq(tag),
+            )
+        })
+        .collect();
+    diesel::insert_into(article_tags::table)
+        .values(&rows)
+        .execute(conn)?;
+    Ok(())
+}
+
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct TagCount {
+    #[sql_type = "Text"]
+    pub tag: String,
+    #[sql_type = "BigInt"]
+    pub count: i64,
+}
+
+/// The tags of published articles, the most used first.
+pub fn popular(conn: &PgConnection) -> Vec<TagCount> {
+    sql_query(
+        "SELECT tags.name AS tag, COUNT(*) AS count FROM tags \
+         JOIN article_tags ON article_tags.tag = tags.id \
+         JOIN articles ON articles.id = article_tags.article \
+         WHERE NOT tags.blocked AND articles.status = $1 \
+         GROUP BY tags.name ORDER BY count DESC, tags.name",
+    )
+    .bind::<Text, _>(Status::Published.as_str())
+    .load(conn)
+    .expect("Cannot load tags")
+}
+
+#[derive(QueryableByName, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminTag {
+    #[sql_type = "Text"]
+    pub name: String,
+    #[sql_type = "Bool"]
+    pub blocked: bool,
+    /// drafts included
+    #[sql_type = "BigInt"]
+    pub articles_count: i64,
+}
+
+/// Every tag, blocked ones too, the most used first.
+pub fn list(conn: &PgConnection) -> Vec<AdminTag> {
+    sql_query(
+        "SELECT tags.name, tags.blocked, COUNT(article_tags.article) AS articles_count \
+         FROM tags LEFT JOIN article_tags ON article_tags.tag = tags.id \
+         GROUP BY tags.id ORDER BY articles_count DESC, tags.name",
+    )
+    .load(conn)
+    .expect("Cannot load tags")
+}
+
+/// Whether the tag is blocked, None if there is no such tag.
+pub fn find(conn: &PgConnection, name: &str) -> Option<bool> {
+    tags::table
+        .select(tags::blocked)
+        .filter(tags::name.eq(name))
+        .get_result(conn)
+        .optional()
+        .expect("Error loading tag")
+}
+
+/// Rewrite the `tag_list` of every article with `name`, returns how many.
+fn rewrite(
+    conn: &PgConnection,
+    name: &str,
+    f: impl Fn(&[String]) -> Vec<String>,
+) -> QueryResult<usize> {
+    let tagged = articles::table
+        .filter(articles::tag_list.contains(vec![name]))
+        .load::<Article>(conn)?;
+    for article in &tagged {
+        let article = diesel::update(articles::table.find(article.id))
+            .set(articles::tag_list.eq(f(&article.tag_list)))
+            .get_result::<Article>(conn)?;
+        sync(conn, &article)?;
+    }
+    Ok(tagged.len())
+}
+
+/// Give a tag a name nobody uses yet, on every article. Returns how many
+/// articles changed.
+pub fn rename(conn: &PgConnection, name: &str, new_name: &str) -> QueryResult<usize> {
+    conn.transaction(|| {
+        diesel::update(tags::table.filter(tags::name.eq(name)))
+            .set(tags::name.eq(new_name))
+            .execute(conn)?;
+        rewrite(conn, name, |tags| {
+            tags.iter()
+                .map(|tag| if tag == name { new_name } else { tag.as_str() }.to_string())
+                .collect()
+        })
+    })
+}
+
+/// Replace a tag by another one and remove it. Returns how many articles changed.
+pub fn merge(conn: &PgConnection, name: &str, into: &str) -> QueryResult<usize> {
+    conn.transaction(|| {
+        let changed = rewrite(conn, name, |tags| {
+            let tags: Vec<String> = tags
+                .iter()
+                .map(|tag| if tag == name { into } else { tag.as_str() }.to_string())
+                .collect();
+            normalize_list(&tags)
+        })?;
+        diesel::delete(tags::table.filter(tags::name.eq(name))).execute(conn)?;
+        Ok(changed)
+    })
+}
+
+/// Refuse a tag from now on and take it off every article, or allow it again.
+/// Returns how many articles changed.
+pub fn set_blocked(conn: &PgConnection, name: &str, blocked: bool) -> QueryResult<usize> {
+    conn.transaction(|| {
+        diesel::insert_into(tags::table)
+            .values((tags::name.eq(name), tags::blocked.eq(blocked)))
+            .on_conflict(tags::name)
+            .do_update()
+            .set(tags::blocked.eq(blocked))
+            .execute(conn)?;
+        if !blocked {
+            return Ok(0);
+        }
+        rewrite(conn, name, |tags| {
+            tags.iter().filter(|tag| *tag != name).cloned().collect()
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_list() {
+        assert_eq!(normalize("  Dragon \t Training "), "dragon training");
+        let tags = vec![
+            String::from("Rust"),
+            String::from(" "),
+            String::from("web  dev"),
+            String::from("rust"),
+        ];
+        assert_eq!(normalize_list(&tags), vec!["rust", "web dev"]);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::config;
+use crate::db::hash_secret;
+use crate::schema::{recovery_codes, totp_credentials};
+use crate::totp;
+use chrono::{DateTime, Duration, Utc};
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::sql_query;
+use diesel::sql_types::{Int4, Timestamptz};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+#[derive(Queryable)]
+struct TotpCredential {
+    user: i32,
+    secret: String,
+    enabled_at: Option<DateTime<Utc>>,
+    last_step: Option<i64>,
+    failures: i32,
+    failed_at: Option<DateTime<Utc>>,
+}
+
+impl TotpCredential {
+    fn is_locked(&self, now: DateTime<Utc>) -> bool {
+        self.failures >= config::TOTP_MAX_FAILURES && !self.lock_expired(now)
+    }
+
+    fn lock_expired(&self, now: DateTime<Utc>) -> bool {
+        self.failed_at
+            .is_none_or(|t| t < now - Duration::minutes(config::TOTP_LOCK_MINUTES))
+    }
+}
+
+fn load(conn: &PgConnection, user_id: i32) -> Option<TotpCredential> {
+    totp_credentials::table
+        .find(user_id)
+        .get_result::<TotpCredential>(conn)
+        .optional()
+        .expect("Error loading totp credential")
+}
+
+pub fn is_enabled(conn: &PgConnection, user_id: i32) -> bool {
+    load(conn, user_id).is_some_and(|c| c.enabled_at.is_some())
+}
+
+/// Store a fresh secret waiting for `confirm`. None when 2FA is already on,
+/// it has to be disabled first.
+pub fn start_enrollment(conn: &PgConnection, user_id: i32) -> Option<String> {
+    if is_enabled(conn, user_id) {
+        return None;
+    }
+    let secret = totp::generate_secret();
+    diesel::insert_into(totp_credentials::table)
+        .values((
+            totp_credentials::user.eq(user_id),
+            totp_credentials::secret.eq(&secret),
+        ))
+        .on_conflict(totp_credentials::user)
+        .do_update()
+        .set((
+            totp_credentials::secret.eq(&secret),
+            totp_credentials::last_step.eq(None::<i64>),
+            totp_credentials::failures.eq(0),
+        ))
+        .execute(conn)
+        .expect("Error storing totp secret");
+    Some(secret)
+}
+
+/// Turn 2FA on once the user proved the app is set up, return the recovery codes.
+pub fn confirm(conn: &PgConnection, user_id: i32, code: &str) -> Option<Vec<String>> {
+    let credential = load(conn, user_id).filter(|c| c.enabled_at.is_none())?;
+    if !check_code(conn, &credential, code) {
+        return None;
+    }
+    diesel::update(totp_credentials::table.find(user_id))
+        .set(totp_credentials::enabled_at.eq(Utc::now()))
+        .execute(conn)
+        .expect("Error enabling totp");
+    Some(regenerate_recovery_codes(conn, user_id))
+}
+
+/// Check the second factor of an enabled account: a code of the app, or an
+/// unused recovery code.
+pub fn check(conn: &PgConnection, user_id: i32, code: &str) -> bool {
+    match load(conn, user_id).filter(|c| c.enabled_at.is_some()) {
+        Some(credential) => check_code(conn, &credential, code),
+        None => false,
+    }
+}
+
+fn check_code(conn: &PgConnection, credential: &TotpCredential, code: &str) -> bool {
+    let now = Utc::now();
+    if credential.is_locked(now) {
+        log::warn!("second factor of user {} is locked", credential.user);
+        return false;
+    }
+
+    let step = totp::verify(&credential.secret, code, now.timestamp())
+        .filter(|&step| credential.last_step.is_none_or(|last| step > last));
+    let accepted = match step {
+        // only one of two concurrent checks of the same code moves last_step
+        Some(step) => {
+            diesel::update(
+                totp_credentials::table.find(credential.user).filter(
+                    totp_credentials::last_step
+                        .is_null()
+                        .or(totp_credentials::last_step.lt(step)),
+                ),
+            )
+            .set((
+                totp_credentials::last_step.eq(step),
+                totp_credentials::failures.eq(0),
+            ))
+            .execute(conn)
+            .expect("Error updating totp credential")
+                == 1
+        }
+        None => credential.enabled_at.is_some() && use_recovery_code(conn, credential.user, code),
+    };
+
+    if !accepted && record_failure(conn, credential.user, now) >= config::TOTP_MAX_FAILURES {
+        log::warn!("second factor of user {} locked", credential.user);
+    }
+    accepted
+}
+
+#[derive(QueryableByName)]
+struct Failures {
+    #[sql_type = "Int4"]
+    failures: i32,
+}
+
+/// Count a wrong code in the database, so concurrent guesses all count, and
+/// return the new number of failures.
+fn record_failure(conn: &PgConnection, user_id: i32, now: DateTime<Utc>) -> i32 {
+    sql_query(
+        "UPDATE totp_credentials SET failures = CASE \
+         WHEN failed_at IS NULL OR failed_at < $2 THEN 1 ELSE failures + 1 END, \
+         failed_at = $3 WHERE \"user\" = $1 RETURNING failures",
+    )
+    .bind::<Int4, _>(user_id)
+    .bind::<Timestamptz, _>(now - Duration::minutes(config::TOTP_LOCK_MINUTES))
+    .bind::<Timestamptz, _>(now)
+    .get_result::<Failures>(conn)
+    .expect("Error updating totp credential")
+    .failures
+}
+
+fn use_recovery_code(conn: &PgConnection, user_id: i32, code: &str) -> bool {
+    let code_hash = hash_secret(&code.trim().to_lowercase());
+    diesel::update(
+        recovery_codes::table
+            .filter(recovery_codes::user.eq(user_id))
+            .filter(recovery_codes::code_hash.eq(code_hash))
+            .filter(recovery_codes::used_at.is_null()),
+    )
+    .set(recovery_codes::used_at.eq(Utc::now()))
+    .execute(conn)
+    .map(|count| count > 0)
+    .expect("Error using recovery code")
+}
+
+fn generate_recovery_code() -> String {
+    let mut rng = thread_rng();
+    let mut part = || -> String {
+        (0..5)
+            .map(|_| rng.sample(Alphanumeric).to_ascii_lowercase())
+            .collect()
+    };
+    format!("{}-{}", part(), part())
+}
+
+/// Replace all recovery codes, the plain codes are shown to the user only now.
+pub fn regenerate_recovery_codes(conn: &PgConnection, user_id: i32) -> Vec<String> {
+    let codes: Vec<String> = (0..config::RECOVERY_CODES)
+        .map(|_| generate_recovery_code())
+        .collect();
+    conn.transaction::<_, Error, _>(|| {
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user.eq(user_id)))
+            .execute(conn)?;
+        let rows: Vec<_> = codes
+            .iter()
+            .map(|code| {
+                (
+                    recovery_codes::user.eq(user_id),
+                    recovery_codes::code_hash.eq(hash_secret(code)),
+                )
+            })
+            .collect();
+        diesel::insert_into(recovery_codes::table)
+            .values(&rows)
+            .execute(conn)
+    })
+    .expect("Error storing recovery codes");
+    codes
+}
+
+pub fn disable(conn: &PgConnection, user_id: i32) {
+    conn.transaction::<_, Error, _>(|| {
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user.eq(user_id)))
+            .execute(conn)?;
+        diesel::delete(totp_credentials::table.find(user_id)).execute(conn)
+    })
+    .expect("Error disabling totp");
+}
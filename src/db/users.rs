@@ -1,10 +1,16 @@
+use crate::db::sessions;
+use crate::db::OffsetLimit;
 use crate::models::user::User;
+use crate::password::{self, validate_strength};
+use crate::policy::Role;
 use crate::schema::users;
-use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::{DateTime, Utc};
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::result::{DatabaseErrorKind, Error};
+use lazy_static::lazy_static;
 use serde::Deserialize;
+use validator::Validate;
 
 #[derive(Insertable)]
 #[table_name = "users"]
@@ -38,7 +44,7 @@ pub fn create(
     email: &str,
     password: &str,
 ) -> Result<User, UserCreationError> {
-    let shash = hash(password.as_bytes(), DEFAULT_COST).unwrap();
+    let shash = password::hash(password);
     let hash = shash.as_str();
 
     let new_user = &NewUser {
@@ -52,29 +58,43 @@ pub fn create(
         .get_result::<User>(conn)
         .map_err(Into::into)
 }
-use std::time::Instant;
+lazy_static! {
+    /// verified against when the email is unknown, so both cases take as long
+    static ref DUMMY_HASH: String = password::hash("not a password");
+}
+
 pub fn login(conn: &PgConnection, email: &str, password: &str) -> Option<User> {
-    let start = Instant::now();
     let user = users::table
         .filter(users::email.eq(email))
         .get_result::<User>(conn)
+        .optional()
         .map_err(|err| eprintln!("login_user: {}", err))
         .ok()?;
-    let elapsed = start.elapsed();
-    println!("Debug: {:?}", elapsed);
 
-    let password_matches = verify(password.as_bytes(), &user.hash).unwrap();
-    let elapsed = start.elapsed();
-    println!("Debug: {:?}", elapsed);
+    let hash = user
+        .as_ref()
+        .map_or(DUMMY_HASH.as_str(), |u| u.hash.as_str());
+    let password_matches = password::verify(password, hash);
 
-    if password_matches {
-        Some(user)
-    } else {
-        eprintln!(
-            "login attempt for '{}' failed: password doesn't match",
-            email
-        );
-        None
+    match user {
+        Some(mut user) if password_matches => {
+            if password::needs_rehash(&user.hash) {
+                // the password is at hand only now, to move it to the current policy
+                let shash = password::hash(password);
+                match diesel::update(users::table.find(user.id))
+                    .set(users::hash.eq(&shash))
+                    .execute(conn)
+                {
+                    Ok(_) => user.hash = shash,
+                    Err(err) => eprintln!("login_user: rehash: {}", err),
+                }
+            }
+            Some(user)
+        }
+        _ => {
+            eprintln!("login attempt for '{}' failed", email);
+            None
+        }
     }
 }
 
@@ -86,8 +106,16 @@ pub fn find(conn: &PgConnection, id: i32) -> Option<User> {
         .ok()
 }
 
+pub fn find_by_username(conn: &PgConnection, username: &str) -> Option<User> {
+    users::table
+        .filter(users::username.eq(username))
+        .get_result(conn)
+        .optional()
+        .expect("Error loading user")
+}
+
 // TODO: remove clone when diesel will allow skipping fields
-#[derive(Deserialize, AsChangeset, Default, Clone, Debug)]
+#[derive(Deserialize, AsChangeset, Validate, Default, Clone, Debug)]
 #[table_name = "users"]
 pub struct UpdateUserData {
     username: Option<String>,
@@ -97,13 +125,24 @@ pub struct UpdateUserData {
 
     // hack to skip the field
     #[column_name = "hash"]
+    #[validate(custom = "validate_strength")]
     password: Option<String>,
 }
 
+impl UpdateUserData {
+    pub fn changes_password(&self) -> bool {
+        self.password.is_some()
+    }
+
+    pub fn changes_email(&self) -> bool {
+        self.email.is_some()
+    }
+}
+
 pub fn update(conn: &PgConnection, id: i32, data: &UpdateUserData) -> Option<User> {
     let shash:Option<String>;
     if data.password != None {
-        let tmp = hash(data.password.as_ref().unwrap().as_bytes(), DEFAULT_COST).unwrap();
+        let tmp = password::hash(data.password.as_ref().unwrap());
         shash = Some(tmp);    
     } else {
         shash=None;
@@ -113,10 +152,28 @@ pub fn update(conn: &PgConnection, id: i32, data: &UpdateUserData) -> Option<Use
         password: shash,
         ..data.clone()
     };
-    diesel::update(users::table.find(id))
-        .set(data)
-        .get_result(conn)
-        .ok()
+    conn.transaction::<_, Error, _>(|| {
+        let old_email = users::table
+            .find(id)
+            .select(users::email)
+            .get_result::<String>(conn)?;
+        let mut user: User = diesel::update(users::table.find(id))
+            .set(data)
+            .get_result(conn)?;
+        if user.email != old_email {
+            // the new address has to be verified again
+            user = diesel::update(users::table.find(id))
+                .set(users::verified_at.eq(None::<DateTime<Utc>>))
+                .get_result(conn)?;
+        }
+        if data.password.is_some() {
+            // logins made with the old password must not survive the change
+            sessions::revoke_all(conn, id)?;
+        }
+        Ok(user)
+    })
+    .map_err(|err| eprintln!("users::update: {}", err))
+    .ok()
 }
 
 pub fn delete(conn: &PgConnection, uid: i32) -> Option<usize> {
@@ -124,3 +181,55 @@ pub fn delete(conn: &PgConnection, uid: i32) -> Option<usize> {
         .execute(conn)
         .ok()
 }
+
+pub fn list(conn: &PgConnection, offset: i64, limit: i64) -> (Vec<User>, i64) {
+    users::table
+        .order(users::id)
+        .offset_and_limit(offset, limit)
+        .load_and_count::<User>(conn)
+        .expect("Cannot load users")
+}
+
+/// The role travels in the access token, so the user's sessions are revoked
+/// for a demotion to take effect at once.
+pub fn set_role(conn: &PgConnection, id: i32, role: Role) -> Option<User> {
+    conn.transaction::<_, Error, _>(|| {
+        let user = diesel::update(users::table.find(id))
+            .set(users::role.eq(role.as_str()))
+            .get_result(conn)?;
+        sessions::revoke_all(conn, id)?;
+        Ok(user)
+    })
+    .map_err(|err| eprintln!("users::set_role: {}", err))
+    .ok()
+}
+
+/// Mark the email verified, only if it is still the address the link was sent to.
+pub fn verify_email(conn: &PgConnection, id: i32, email: &str) -> bool {
+    diesel::update(
+        users::table
+            .find(id)
+            .filter(users::email.eq(email))
+            .filter(users::verified_at.is_null()),
+    )
+    .set(users::verified_at.eq(Utc::now()))
+    .execute(conn)
+    .map(|count| count > 0)
+    .unwrap_or_else(|err| {
+        eprintln!("users::verify_email: {}", err);
+        false
+    })
+}
+
+pub fn is_verified(conn: &PgConnection, id: i32) -> bool {
+    use diesel::dsl::exists;
+    use diesel::select;
+
+    select(exists(
+        users::table
+            .find(id)
+            .filter(users::verified_at.is_not_null()),
+    ))
+    .get_result(conn)
+    .expect("Error loading user")
+}
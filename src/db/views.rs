@@ -0,0 +1,279 @@
+//! Article views are counted in memory and written in daily buckets every
+//! `VIEWS_FLUSH_SECONDS`, so reading an article doesn't write to the database.
+//! A reader counts once per article in `VIEW_WINDOW_MINUTES`: a user by id,
+//! anyone else by a hash of the address and user agent. The readers are only
+//! known to this server, several servers each count them once.
+use crate::config;
+use crate::db::hash_secret;
+use crate::db::sessions::Device;
+use crate::schema::article_views;
+use crate::schema::articles;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Date, Int4, Text};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref VIEWS: Mutex<Views> = Mutex::new(Views::default());
+}
+
+/// Views by article and day.
+type Buckets = HashMap<(i32, NaiveDate), i32>;
+
+#[derive(Default)]
+struct Views {
+    /// when a reader last counted for an article
+    seen: HashMap<(i32, String), DateTime<Utc>>,
+    /// counted and not written yet
+    pending: Buckets,
+}
+
+impl Views {
+    /// Count a view unless the reader counted in the window, true if it did.
+    fn count(&mut self, article: i32, reader: String, now: DateTime<Utc>) -> bool {
+        let window = Duration::minutes(config::VIEW_WINDOW_MINUTES);
+        let key = (article, reader);
+        if let Some(at) = self.seen.get(&key) {
+            if now - *at < window {
+                return false;
+            }
+        }
+        if self.seen.len() >= config::VIEW_READERS_MAX {
+            self.forget_oldest(now, config::VIEW_READERS_MAX);
+        }
+        self.seen.insert(key, now);
+        *self.pending.entry((article, now.date_naive())).or_insert(0) += 1;
+        true
+    }
+
+    /// Make room below `max` readers: those out of the window go first, then
+    /// the oldest tenth of the rest, so a full map isn't trimmed at every view.
+    fn forget_oldest(&mut self, now: DateTime<Utc>, max: usize) {
+        let window = Duration::minutes(config::VIEW_WINDOW_MINUTES);
+        self.seen.retain(|_, at| now - *at < window);
+        if self.seen.len() < max {
+            return;
+        }
+        let mut times: Vec<DateTime<Utc>> = self.seen.values().copied().collect();
+        let keep = max - max / 10 - 1;
+        let index = times.len() - 1 - keep;
+        let cutoff = *times.select_nth_unstable(index).1;
+        self.seen.retain(|_, at| *at > cutoff);
+    }
+
+    /// The views to write, readers whose window is over are forgotten.
+    fn take(&mut self, now: DateTime<Utc>) -> Buckets {
+        let window = Duration::minutes(config::VIEW_WINDOW_MINUTES);
+        self.seen.retain(|_, at| now - *at < window);
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Put back views that couldn't be written.
+    fn restore(&mut self, buckets: Buckets) {
+        for (key, views) in buckets {
+            *self.pending.entry(key).or_insert(0) += views;
+        }
+    }
+}
+
+/// Drop the views of articles deleted since they were counted.
+fn retain_existing(buckets: &mut Buckets, existing: &HashSet<i32>) {
+    buckets.retain(|(article, _), _| existing.contains(article));
+}
+
+/// A write that failed on a constraint fails the same way the next time.
+fn is_transient(err: &Error) -> bool {
+    !matches!(
+        err,
+        Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)
+            | Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)
+    )
+}
+
+/// Who is reading, without keeping the address of anonymous readers.
+pub fn reader(user_id: Option<i32>, device: &Device) -> String {
+    match user_id {
+        Some(id) => format!("user:{}", id),
+        None => hash_secret(&format!(
+            "{:?}|{}",
+            device.ip,
+            device.user_agent.as_deref().unwrap_or_default()
+        )),
+    }
+}
+
+/// Count a view of an article, it is written with the next `flush`.
+pub fn record(article_id: i32, reader: String) {
+    VIEWS.lock().unwrap().count(article_id, reader, Utc::now());
+}
+
+/// Write the views counted since the last time, returns how many. Views of
+/// deleted articles are dropped.
+pub fn flush(conn: &PgConnection) -> QueryResult<i32> {
+    let buckets = VIEWS.lock().unwrap().take(Utc::now());
+    if buckets.is_empty() {
+        return Ok(0);
+    }
+    let written = conn.transaction::<_, Error, _>(|| {
+        let ids: Vec<i32> = buckets.keys().map(|&(article, _)| article).collect();
+        // locked so they can't be deleted before the views are written
+        let existing: HashSet<i32> = articles::table
+            .select(articles::id)
+            .filter(articles::id.eq_any(ids))
+            .for_key_share()
+            .load::<i32>(conn)?
+            .into_iter()
+            .collect();
+        let mut buckets = buckets.clone();
+        retain_existing(&mut buckets, &existing);
+
+        let mut by_article: HashMap<i32, i32> = HashMap::new();
+        for (&(article, day), &views) in &buckets {
+            diesel::insert_into(article_views::table)
+                .values((
+                    article_views::article.eq(article),
+                    article_views::day.eq(day),
+                    article_views::views.eq(views),
+                ))
+                .on_conflict((article_views::article, article_views::day))
+                .do_update()
+                .set(article_views::views.eq(article_views::views + views))
+                .execute(conn)?;
+            *by_article.entry(article).or_insert(0) += views;
+        }
+        for (&article, &views) in &by_article {
+            diesel::update(articles::table.find(article))
+                .set(articles::views_count.eq(articles::views_count + views))
+                .execute(conn)?;
+        }
+        Ok(by_article.values().sum())
+    });
+    match written {
+        Err(ref err) if is_transient(err) => VIEWS.lock().unwrap().restore(buckets),
+        _ => {}
+    }
+    written
+}
+
+/// How an article did in a range of days.
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct ArticleStats {
+    #[sql_type = "Text"]
+    pub slug: String,
+    #[sql_type = "Text"]
+    pub title: String,
+    #[sql_type = "BigInt"]
+    pub views: i64,
+    #[sql_type = "BigInt"]
+    pub favorites: i64,
+    #[sql_type = "BigInt"]
+    pub comments: i64,
+}
+
+/// Views, favorites and comments of the articles `author` wrote or co-authors
+/// from `from` to `to`, both included, in days of UTC; the most viewed first.
+pub fn analytics(
+    conn: &PgConnection,
+    author: i32,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Vec<ArticleStats> {
+    sql_query(
+        "SELECT articles.slug, articles.title, \
+         COALESCE((SELECT SUM(article_views.views) FROM article_views \
+           WHERE article_views.article = articles.id \
+           AND article_views.day BETWEEN $2 AND $3), 0)::BIGINT AS views, \
+         (SELECT COUNT(*) FROM favorites WHERE favorites.article = articles.id \
+           AND (favorites.created_at AT TIME ZONE 'UTC')::date BETWEEN $2 AND $3) AS favorites, \
+         (SELECT COUNT(*) FROM comments WHERE comments.article = articles.id \
+           AND (comments.created_at AT TIME ZONE 'UTC')::date BETWEEN $2 AND $3) AS comments \
+         FROM articles WHERE articles.author = $1 \
+         OR articles.id IN (SELECT article FROM article_authors \
+           WHERE \"user\" = $1 AND accepted_at IS NOT NULL) \
+         ORDER BY views DESC, articles.id DESC",
+    )
+    .bind::<Int4, _>(author)
+    .bind::<Date, _>(from)
+    .bind::<Date, _>(to)
+    .load(conn)
+    .expect("Cannot load analytics")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_views() {
+        let mut views = Views::default();
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 23, 50, 0).unwrap();
+        assert!(views.count(1, String::from("user:7"), now));
+        assert!(!views.count(1, String::from("user:7"), now + Duration::minutes(10)));
+        assert!(views.count(2, String::from("user:7"), now));
+        assert!(views.count(1, String::from("user:8"), now));
+        // a new window, and a new day
+        assert!(views.count(1, String::from("user:7"), now + Duration::minutes(40)));
+
+        let day = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+        let buckets = views.take(now + Duration::minutes(40));
+        assert_eq!(buckets.len(), 3);
+        assert_eq!(buckets[&(1, day)], 2);
+        assert_eq!(buckets[&(2, day)], 1);
+        assert_eq!(buckets[&(1, day.succ_opt().unwrap())], 1);
+        assert!(views.pending.is_empty());
+        // only the reader of 23:50 + 40 minutes is still in the window
+        assert_eq!(views.seen.len(), 1);
+
+        views.restore(buckets);
+        assert_eq!(views.pending[&(1, day)], 2);
+    }
+
+    #[test]
+    fn test_deleted_article() {
+        let mut views = Views::default();
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+        views.count(1, String::from("user:7"), now);
+        views.count(2, String::from("user:7"), now);
+        // article 2 is deleted before the flush
+        let mut buckets = views.take(now);
+        retain_existing(&mut buckets, &[1].iter().copied().collect());
+        let day = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[&(1, day)], 1);
+
+        let fk = Error::DatabaseError(
+            DatabaseErrorKind::ForeignKeyViolation,
+            Box::new(String::from("article_views_article_fkey")),
+        );
+        assert!(!is_transient(&fk));
+        assert!(is_transient(&Error::RollbackTransaction));
+    }
+
+    #[test]
+    fn test_forget_oldest() {
+        let mut views = Views::default();
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+        let reader = |i| format!("user:{}", i);
+        views.seen.insert((1, reader(0)), now - Duration::hours(1));
+        for i in 1..=20 {
+            views
+                .seen
+                .insert((1, reader(i)), now + Duration::seconds(i));
+        }
+        views.forget_oldest(now + Duration::minutes(1), 20);
+        // the reader out of the window and the three oldest in it are gone
+        assert_eq!(views.seen.len(), 17);
+        assert!(!views.seen.contains_key(&(1, reader(0))));
+        assert!(!views.seen.contains_key(&(1, reader(3))));
+        assert!(views.seen.contains_key(&(1, reader(4))));
+        assert!(views.seen.contains_key(&(1, reader(20))));
+    }
+}
@@ -0,0 +1,165 @@
+//! Line based unified diffs, as `diff -u` prints them.
+use std::cmp::max;
+
+/// unchanged lines shown around a change
+const CONTEXT: usize = 3;
+/// past this many line pairs the changed middle is shown as replaced as a whole
+const MAX_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// The edits turning `a` into `b`, from their longest common subsequence.
+fn ops<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (am, bm) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut ops: Vec<_> = a[..prefix].iter().map(|line| (Op::Equal, *line)).collect();
+    if am.len() * bm.len() > MAX_CELLS {
+        ops.extend(am.iter().map(|line| (Op::Delete, *line)));
+        ops.extend(bm.iter().map(|line| (Op::Insert, *line)));
+    } else {
+        // lcs[i * w + j] is the length of the common subsequence of am[i..] and bm[j..]
+        let w = bm.len() + 1;
+        let mut lcs = vec![0u32; (am.len() + 1) * w];
+        for i in (0..am.len()).rev() {
+            for j in (0..bm.len()).rev() {
+                lcs[i * w + j] = if am[i] == bm[j] {
+                    lcs[(i + 1) * w + j + 1] + 1
+                } else {
+                    max(lcs[(i + 1) * w + j], lcs[i * w + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < am.len() && j < bm.len() {
+            if am[i] == bm[j] {
+                ops.push((Op::Equal, am[i]));
+                i += 1;
+                j += 1;
+            } else if lcs[(i + 1) * w + j] >= lcs[i * w + j + 1] {
+                ops.push((Op::Delete, am[i]));
+                i += 1;
+            } else {
+                ops.push((Op::Insert, bm[j]));
+                j += 1;
+            }
+        }
+        ops.extend(am[i..].iter().map(|line| (Op::Delete, *line)));
+        ops.extend(bm[j..].iter().map(|line| (Op::Insert, *line)));
+    }
+    ops.extend(a[a.len() - suffix..].iter().map(|line| (Op::Equal, *line)));
+    ops
+}
+
+/// `start` is 0-based, an empty range names the line before it.
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// The unified diff from `old` to `new`, empty when they are the same.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let ops = ops(&a, &b);
+    let changes: Vec<usize> = (0..ops.len()).filter(|&k| ops[k].0 != Op::Equal).collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // the old and new line before each op
+    let mut starts = Vec::with_capacity(ops.len());
+    let (mut o, mut n) = (0, 0);
+    for (op, _) in &ops {
+        starts.push((o, n));
+        match op {
+            Op::Equal => {
+                o += 1;
+                n += 1;
+            }
+            Op::Delete => o += 1,
+            Op::Insert => n += 1,
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    let mut k = 0;
+    while k < changes.len() {
+        let first = changes[k];
+        let mut last = first;
+        // changes with little in between share a hunk
+        while k + 1 < changes.len() && changes[k + 1] - last <= 2 * CONTEXT + 1 {
+            k += 1;
+            last = changes[k];
+        }
+        k += 1;
+
+        let from = first.saturating_sub(CONTEXT);
+        let to = (last + CONTEXT + 1).min(ops.len());
+        let hunk = &ops[from..to];
+        let (o, n) = starts[from];
+        let old_len = hunk.iter().filter(|(op, _)| *op != Op::Insert).count();
+        let new_len = hunk.iter().filter(|(op, _)| *op != Op::Delete).count();
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(o, old_len),
+            range(n, new_len)
+        ));
+        for (op, line) in hunk {
+            out.push(match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            });
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified() {
+        assert_eq!(unified("a\nb\n", "a\nb\n", "a", "b"), "");
+        assert_eq!(
+            unified("a\nb\nc\n", "a\nx\nc\nd\n", "old", "new"),
+            "--- old\n+++ new\n@@ -1,3 +1,4 @@\n a\n-b\n+x\n c\n+d\n"
+        );
+        assert_eq!(
+            unified("", "hello\n", "old", "new"),
+            "--- old\n+++ new\n@@ -0,0 +1 @@\n+hello\n"
+        );
+    }
+
+    #[test]
+    fn test_unified_hunks() {
+        let old: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        let new = old
+            .replace("line 2\n", "line two\n")
+            .replace("line 19\n", "line nineteen\n");
+        assert_eq!(
+            unified(&old, &new, "old", "new"),
+            "--- old\n+++ new\n\
+             @@ -1,5 +1,5 @@\n line 1\n-line 2\n+line two\n line 3\n line 4\n line 5\n\
+             @@ -16,5 +16,5 @@\n line 16\n line 17\n line 18\n-line 19\n+line nineteen\n line 20\n"
+        );
+    }
+}
@@ -4,6 +4,7 @@ use std::collections::HashMap;
 use std::convert::Infallible;
 use validator::{Validate, ValidationError, ValidationErrors};
 use warp::http::StatusCode;
+use warp::{reject, Rejection, Reply};
 
 #[derive(Debug, Serialize)]
 pub struct Errors {
@@ -35,13 +36,6 @@ impl Errors {
 
         json!({ "errors": errors })
     }
-    pub fn respond_to(self) -> Result<warp::reply::WithStatus<warp::reply::Json>, Infallible> {
-        let jsonerr = self.get_json();
-        Ok(warp::reply::with_status(
-            warp::reply::json(&jsonerr),
-            StatusCode::BAD_REQUEST,
-        ))
-    }
     /*
     pub fn respond_without_status(self) -> Result<impl warp::Reply, Infallible> {
         let jsonerr=self.get_json();
@@ -49,6 +43,130 @@ impl Errors {
     }*/
 }
 
+/// Why a request failed, turned into the response by `recover`.
+#[derive(Debug)]
+pub enum ApiError {
+    /// 401, no login or its token is invalid, expired or revoked
+    Unauthorized(Errors),
+    /// 403, logged in but not allowed to
+    Forbidden(Errors),
+    /// 404
+    NotFound(Errors),
+    /// 409, e.g. a username that is taken
+    Conflict(Errors),
+    /// 422, the request doesn't make sense
+    Validation(Errors),
+    /// 429, try again after the seconds given
+    TooManyRequests(Errors, i64),
+    /// 500, details are only logged
+    Internal,
+}
+
+impl reject::Reject for ApiError {}
+
+/// Field errors are 422, so `extractor.check()?` works in handlers.
+impl From<Errors> for Rejection {
+    fn from(errors: Errors) -> Rejection {
+        reject::custom(ApiError::Validation(errors))
+    }
+}
+
+impl ApiError {
+    pub fn unauthorized() -> Rejection {
+        ApiError::Unauthorized(Errors::new(&[("token", "is missing, invalid or expired")])).into()
+    }
+
+    /// A password, code or token that was sent along doesn't match.
+    pub fn bad_credentials(field: FieldName) -> Rejection {
+        ApiError::Unauthorized(Errors::new(&[(field, "is invalid")])).into()
+    }
+
+    pub fn forbidden(field: FieldName, code: FieldErrorCode) -> Rejection {
+        ApiError::Forbidden(Errors::new(&[(field, code)])).into()
+    }
+
+    pub fn not_found(field: FieldName) -> Rejection {
+        ApiError::NotFound(Errors::new(&[(field, "not found")])).into()
+    }
+
+    pub fn conflict(field: FieldName, code: FieldErrorCode) -> Rejection {
+        ApiError::Conflict(Errors::new(&[(field, code)])).into()
+    }
+
+    pub fn validation(field: FieldName, code: FieldErrorCode) -> Rejection {
+        ApiError::Validation(Errors::new(&[(field, code)])).into()
+    }
+
+    pub fn too_many_requests(field: FieldName, code: FieldErrorCode, seconds: i64) -> Rejection {
+        ApiError::TooManyRequests(Errors::new(&[(field, code)]), seconds).into()
+    }
+
+    pub fn internal() -> Rejection {
+        ApiError::Internal.into()
+    }
+}
+
+fn error_reply(status: StatusCode, errors: Errors) -> warp::reply::Response {
+    warp::reply::with_status(warp::reply::json(&errors.get_json()), status).into_response()
+}
+
+/// The one place rejections become responses, all with an `{"errors": ...}` body.
+pub async fn recover(rejection: Rejection) -> Result<warp::reply::Response, Infallible> {
+    let reply = if let Some(err) = rejection.find::<ApiError>() {
+        // the rejection only lends us the error
+        let errors = |errors: &Errors| Errors {
+            errors: errors.errors.clone(),
+        };
+        match err {
+            ApiError::Unauthorized(e) => error_reply(StatusCode::UNAUTHORIZED, errors(e)),
+            ApiError::Forbidden(e) => error_reply(StatusCode::FORBIDDEN, errors(e)),
+            ApiError::NotFound(e) => error_reply(StatusCode::NOT_FOUND, errors(e)),
+            ApiError::Conflict(e) => error_reply(StatusCode::CONFLICT, errors(e)),
+            ApiError::Validation(e) => error_reply(StatusCode::UNPROCESSABLE_ENTITY, errors(e)),
+            ApiError::TooManyRequests(e, seconds) => warp::reply::with_header(
+                error_reply(StatusCode::TOO_MANY_REQUESTS, errors(e)),
+                "Retry-After",
+                seconds.to_string(),
+            )
+            .into_response(),
+            ApiError::Internal => error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Errors::new(&[("server", "internal error")]),
+            ),
+        }
+    } else if rejection.is_not_found() {
+        error_reply(StatusCode::NOT_FOUND, Errors::new(&[("path", "not found")]))
+    } else if let Some(err) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        log::debug!("bad request body: {}", err);
+        error_reply(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Errors::new(&[("body", "is invalid")]),
+        )
+    } else if rejection.find::<reject::InvalidQuery>().is_some() {
+        error_reply(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Errors::new(&[("query", "is invalid")]),
+        )
+    } else if rejection.find::<reject::MethodNotAllowed>().is_some() {
+        error_reply(
+            StatusCode::METHOD_NOT_ALLOWED,
+            Errors::new(&[("method", "not allowed")]),
+        )
+    } else if rejection.find::<reject::PayloadTooLarge>().is_some() {
+        error_reply(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Errors::new(&[("body", "is too large")]),
+        )
+    } else {
+        log::error!("unhandled rejection: {:?}", rejection);
+        error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Errors::new(&[("server", "internal error")]),
+        )
+    };
+    Ok(reply)
+}
+
 pub struct FieldValidator {
     errors: ValidationErrors,
 }
@@ -0,0 +1,503 @@
+//! Signing keys for the JWTs.
+//!
+//! Without `JWT_KEYRING` tokens are signed HS256 with `SECRET_KEY`, as before.
+//! With it, the keyring file lists the asymmetric keys (RS*, ES* or EdDSA
+//! with Ed25519 keys), e.g.
+//!
+//! ```json
+//! [
+//!   { "kid": "2026-10", "alg": "RS256",
+//!     "private_key": "keys/2026-10.pem", "public_key": "keys/2026-10.pub.pem",
+//!     "not_before": "2026-10-01T00:00:00Z" },
+//!   { "kid": "2026-07", "alg": "ES256",
+//!     "public_key": "keys/2026-07.pub.pem",
+//!     "not_after": "2026-11-01T00:00:00Z" }
+//! ]
+//! ```
+//!
+//! The newest key whose `not_before` has passed signs new tokens, every key
+//! keeps verifying (and stays in the JWKS) until its `not_after`. A rotation is
+//! scheduled by adding the next key with a future `not_before`: it is published
+//! in the JWKS at once, so other services can fetch it before it signs anything.
+use crate::db;
+use chrono::{DateTime, Utc};
+use frank_jwt::{Algorithm, ValidationOptions};
+use lazy_static::lazy_static;
+use openssl::bn::{BigNum, BigNumContext, BigNumRef};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey};
+use openssl::rsa::Rsa;
+use openssl::sign::{Signer, Verifier};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs;
+
+/// kid of the HS256 key built from `SECRET_KEY`
+pub const DEFAULT_KID: &str = "default";
+
+#[derive(Deserialize, Debug)]
+struct KeyEntry {
+    kid: String,
+    alg: String,
+    private_key: Option<String>,
+    public_key: String,
+    not_before: Option<DateTime<Utc>>,
+    not_after: Option<DateTime<Utc>>,
+}
+
+/// What a key signs with. frank_jwt has no EdDSA, Ed25519 tokens are signed
+/// and verified with openssl here.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Alg {
+    Jwt(Algorithm),
+    EdDSA,
+}
+
+impl fmt::Debug for Alg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(alg_name(*self))
+    }
+}
+
+pub struct Key {
+    pub kid: String,
+    pub alg: Alg,
+    /// PEM of the private key, or the shared secret for HS*
+    signing: Option<Vec<u8>>,
+    /// PEM of the public key, or the shared secret for HS*
+    verifying: Vec<u8>,
+    not_before: Option<DateTime<Utc>>,
+    not_after: Option<DateTime<Utc>>,
+}
+
+/// The key material stays out of logs.
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Key")
+            .field("kid", &self.kid)
+            .field("alg", &self.alg)
+            .field("not_before", &self.not_before)
+            .field("not_after", &self.not_after)
+            .finish()
+    }
+}
+
+impl Key {
+    pub fn signing_key(&self) -> Option<&Vec<u8>> {
+        self.signing.as_ref()
+    }
+
+    pub fn verifying_key(&self) -> &Vec<u8> {
+        &self.verifying
+    }
+
+    fn is_symmetric(&self) -> bool {
+        matches!(
+            self.alg,
+            Alg::Jwt(Algorithm::HS256) | Alg::Jwt(Algorithm::HS384) | Alg::Jwt(Algorithm::HS512)
+        )
+    }
+
+    fn can_sign_at(&self, now: DateTime<Utc>) -> bool {
+        self.signing.is_some()
+            && self.not_before.is_none_or(|t| t <= now)
+            && self.not_after.is_none_or(|t| now < t)
+    }
+
+    fn can_verify_at(&self, now: DateTime<Utc>) -> bool {
+        self.not_after.is_none_or(|t| now < t)
+    }
+
+    /// Public part of the key as a JWK, None for symmetric keys.
+    fn to_jwk(&self) -> Option<Value> {
+        if self.is_symmetric() {
+            return None;
+        }
+        let pkey = PKey::public_key_from_pem(&self.verifying)
+            .map_err(|err| log::error!("key {}: {}", self.kid, err))
+            .ok()?;
+        let alg = alg_name(self.alg);
+        match pkey.id() {
+            Id::RSA => {
+                let rsa = pkey.rsa().ok()?;
+                Some(json!({
+                    "kty": "RSA",
+                    "use": "sig",
+                    "kid": self.kid,
+                    "alg": alg,
+                    "n": b64(rsa.n()),
+                    "e": b64(rsa.e()),
+                }))
+            }
+            Id::EC => {
+                let ec = pkey.ec_key().ok()?;
+                let crv = match self.alg {
+                    Alg::Jwt(Algorithm::ES256) => "P-256",
+                    Alg::Jwt(Algorithm::ES384) => "P-384",
+                    _ => "P-521",
+                };
+                let mut ctx = BigNumContext::new().ok()?;
+                let mut x = openssl::bn::BigNum::new().ok()?;
+                let mut y = openssl::bn::BigNum::new().ok()?;
+                ec.public_key()
+                    .affine_coordinates_gfp(ec.group(), &mut x, &mut y, &mut ctx)
+                    .ok()?;
+                Some(json!({
+                    "kty": "EC",
+                    "use": "sig",
+                    "kid": self.kid,
+                    "alg": alg,
+                    "crv": crv,
+                    "x": b64(&x),
+                    "y": b64(&y),
+                }))
+            }
+            Id::ED25519 => Some(json!({
+                "kty": "OKP",
+                "use": "sig",
+                "kid": self.kid,
+                "alg": alg,
+                "crv": "Ed25519",
+                "x": base64::encode_config(&pkey.raw_public_key().ok()?, base64::URL_SAFE_NO_PAD),
+            })),
+            _ => None,
+        }
+    }
+}
+
+fn b64(n: &BigNumRef) -> String {
+    base64::encode_config(n.to_vec(), base64::URL_SAFE_NO_PAD)
+}
+
+fn unb64(s: &Value) -> Option<BigNum> {
+    let bytes = base64::decode_config(s.as_str()?, base64::URL_SAFE_NO_PAD).ok()?;
+    BigNum::from_slice(&bytes).ok()
+}
+
+/// PEM of the public key in a JWK of somebody else's JWKS, with its algorithm.
+/// Only asymmetric keys, a shared secret has no place in a JWKS.
+pub fn jwk_to_pem(jwk: &Value) -> Option<(Alg, Vec<u8>)> {
+    let pkey = match jwk["kty"].as_str()? {
+        "RSA" => {
+            let rsa = Rsa::from_public_components(unb64(&jwk["n"])?, unb64(&jwk["e"])?).ok()?;
+            PKey::from_rsa(rsa).ok()?
+        }
+        "EC" => {
+            let nid = match jwk["crv"].as_str()? {
+                "P-256" => Nid::X9_62_PRIME256V1,
+                "P-384" => Nid::SECP384R1,
+                "P-521" => Nid::SECP521R1,
+                _ => return None,
+            };
+            let group = EcGroup::from_curve_name(nid).ok()?;
+            let (x, y) = (unb64(&jwk["x"])?, unb64(&jwk["y"])?);
+            let ec = EcKey::from_public_key_affine_coordinates(&group, &x, &y).ok()?;
+            PKey::from_ec_key(ec).ok()?
+        }
+        "OKP" if jwk["crv"] == "Ed25519" => {
+            let x = base64::decode_config(jwk["x"].as_str()?, base64::URL_SAFE_NO_PAD).ok()?;
+            PKey::public_key_from_raw_bytes(&x, Id::ED25519).ok()?
+        }
+        _ => return None,
+    };
+    let alg = match jwk["alg"].as_str() {
+        Some(alg) => parse_alg(alg).ok()?,
+        None if pkey.id() == Id::RSA => Alg::Jwt(Algorithm::RS256),
+        None if pkey.id() == Id::ED25519 => Alg::EdDSA,
+        None => Alg::Jwt(Algorithm::ES256),
+    };
+    match (alg, pkey.id()) {
+        (Alg::Jwt(Algorithm::RS256), Id::RSA)
+        | (Alg::Jwt(Algorithm::RS384), Id::RSA)
+        | (Alg::Jwt(Algorithm::RS512), Id::RSA)
+        | (Alg::Jwt(Algorithm::ES256), Id::EC)
+        | (Alg::Jwt(Algorithm::ES384), Id::EC)
+        | (Alg::Jwt(Algorithm::ES512), Id::EC)
+        | (Alg::EdDSA, Id::ED25519) => Some((alg, pkey.public_key_to_pem().ok()?)),
+        _ => None,
+    }
+}
+
+fn parse_alg(alg: &str) -> Result<Alg, String> {
+    match alg {
+        "HS256" => Ok(Alg::Jwt(Algorithm::HS256)),
+        "HS384" => Ok(Alg::Jwt(Algorithm::HS384)),
+        "HS512" => Ok(Alg::Jwt(Algorithm::HS512)),
+        "RS256" => Ok(Alg::Jwt(Algorithm::RS256)),
+        "RS384" => Ok(Alg::Jwt(Algorithm::RS384)),
+        "RS512" => Ok(Alg::Jwt(Algorithm::RS512)),
+        "ES256" => Ok(Alg::Jwt(Algorithm::ES256)),
+        "ES384" => Ok(Alg::Jwt(Algorithm::ES384)),
+        "ES512" => Ok(Alg::Jwt(Algorithm::ES512)),
+        "EdDSA" => Ok(Alg::EdDSA),
+        _ => Err(format!("unsupported signing algorithm: {}", alg)),
+    }
+}
+
+fn alg_name(alg: Alg) -> &'static str {
+    match alg {
+        Alg::Jwt(Algorithm::HS256) => "HS256",
+        Alg::Jwt(Algorithm::HS384) => "HS384",
+        Alg::Jwt(Algorithm::HS512) => "HS512",
+        Alg::Jwt(Algorithm::RS256) => "RS256",
+        Alg::Jwt(Algorithm::RS384) => "RS384",
+        Alg::Jwt(Algorithm::RS512) => "RS512",
+        Alg::Jwt(Algorithm::ES256) => "ES256",
+        Alg::Jwt(Algorithm::ES384) => "ES384",
+        Alg::Jwt(Algorithm::ES512) => "ES512",
+        Alg::EdDSA => "EdDSA",
+    }
+}
+
+fn b64_json(value: &Value) -> String {
+    base64::encode_config(value.to_string().as_bytes(), base64::URL_SAFE_NO_PAD)
+}
+
+/// Sign a JWT with a key of `alg`, which goes in the header with `typ`.
+pub fn encode(mut header: Value, key: &[u8], payload: &Value, alg: Alg) -> Result<String, String> {
+    match alg {
+        Alg::Jwt(alg) => frank_jwt::encode(header, &key.to_vec(), payload, alg)
+            .map_err(|err| format!("{:?}", err)),
+        Alg::EdDSA => {
+            header["alg"] = json!("EdDSA");
+            header["typ"] = json!("JWT");
+            let input = format!("{}.{}", b64_json(&header), b64_json(payload));
+            let pkey = PKey::private_key_from_pem(key).map_err(|err| err.to_string())?;
+            let signature = Signer::new_without_digest(&pkey)
+                .and_then(|mut signer| signer.sign_oneshot_to_vec(input.as_bytes()))
+                .map_err(|err| err.to_string())?;
+            Ok(format!(
+                "{}.{}",
+                input,
+                base64::encode_config(&signature, base64::URL_SAFE_NO_PAD)
+            ))
+        }
+    }
+}
+
+/// Verify the signature and the `exp` of a JWT, return its payload.
+pub fn decode(token: &str, key: &[u8], alg: Alg) -> Result<Value, String> {
+    match alg {
+        Alg::Jwt(alg) => {
+            frank_jwt::decode(token, &key.to_vec(), alg, &ValidationOptions::default())
+                .map(|(_, payload)| payload)
+                .map_err(|err| format!("{:?}", err))
+        }
+        Alg::EdDSA => {
+            let segments: Vec<&str> = token.split('.').collect();
+            if segments.len() != 3 {
+                return Err(String::from("malformed token"));
+            }
+            if token_header(token).is_none_or(|header| header["alg"] != "EdDSA") {
+                return Err(String::from("token is not EdDSA"));
+            }
+            let unb64 = |segment: &str| {
+                base64::decode_config(segment, base64::URL_SAFE_NO_PAD)
+                    .map_err(|err| err.to_string())
+            };
+            let signature = unb64(segments[2])?;
+            let input = format!("{}.{}", segments[0], segments[1]);
+            let pkey = PKey::public_key_from_pem(key).map_err(|err| err.to_string())?;
+            let valid = Verifier::new_without_digest(&pkey)
+                .and_then(|mut verifier| verifier.verify_oneshot(&signature, input.as_bytes()))
+                .map_err(|err| err.to_string())?;
+            if !valid {
+                return Err(String::from("signature invalid"));
+            }
+            let payload: Value =
+                serde_json::from_slice(&unb64(segments[1])?).map_err(|err| err.to_string())?;
+            if payload["exp"]
+                .as_i64()
+                .is_none_or(|exp| exp <= Utc::now().timestamp())
+            {
+                return Err(String::from("token expired"));
+            }
+            Ok(payload)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Keyring {
+    keys: Vec<Key>,
+}
+
+impl Keyring {
+    fn load() -> Result<Keyring, String> {
+        let path = match db::CONFIG.jwt_keyring {
+            Some(ref path) => path,
+            None => {
+                let secret = db::CONFIG.secret_key.as_bytes().to_vec();
+                return Ok(Keyring {
+                    keys: vec![Key {
+                        kid: DEFAULT_KID.to_string(),
+                        alg: Alg::Jwt(Algorithm::HS256),
+                        signing: Some(secret.clone()),
+                        verifying: secret,
+                        not_before: None,
+                        not_after: None,
+                    }],
+                });
+            }
+        };
+
+        let content = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        let entries: Vec<KeyEntry> =
+            serde_json::from_str(&content).map_err(|err| format!("{}: {}", path, err))?;
+        let mut keys = Vec::new();
+        for entry in entries {
+            let alg = parse_alg(&entry.alg)?;
+            let read = |file: &String| fs::read(file).map_err(|err| format!("{}: {}", file, err));
+            let signing = match entry.private_key {
+                Some(ref file) => Some(read(file)?),
+                None => None,
+            };
+            keys.push(Key {
+                kid: entry.kid,
+                alg,
+                signing,
+                verifying: read(&entry.public_key)?,
+                not_before: entry.not_before,
+                not_after: entry.not_after,
+            });
+        }
+        Ok(Keyring { keys })
+    }
+
+    /// The key new tokens are signed with.
+    pub fn signing(&self) -> Option<&Key> {
+        let now = Utc::now();
+        self.keys
+            .iter()
+            .filter(|key| key.can_sign_at(now))
+            .max_by_key(|key| key.not_before)
+    }
+
+    /// The key a token is verified with, picked by the `kid` of its header.
+    /// Tokens without a `kid` were signed before the keyring existed.
+    pub fn verifying(&self, kid: Option<&str>) -> Option<&Key> {
+        let kid = kid.unwrap_or(DEFAULT_KID);
+        let now = Utc::now();
+        self.keys
+            .iter()
+            .find(|key| key.kid == kid && key.can_verify_at(now))
+    }
+
+    pub fn jwks(&self) -> Value {
+        let now = Utc::now();
+        let keys: Vec<Value> = self
+            .keys
+            .iter()
+            .filter(|key| key.can_verify_at(now))
+            .filter_map(Key::to_jwk)
+            .collect();
+        json!({ "keys": keys })
+    }
+}
+
+lazy_static! {
+    pub static ref KEYRING: Keyring = Keyring::load().expect("Cannot load JWT keyring");
+}
+
+/// Read the header of a JWT without verifying it.
+pub fn token_header(token: &str) -> Option<Value> {
+    let header = token.split('.').next()?;
+    let bytes = base64::decode_config(header, base64::URL_SAFE_NO_PAD).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Refuse to sign tokens with the secret everybody can read in the source,
+/// unless `DEV_MODE` is set.
+pub fn check_startup() -> Result<(), String> {
+    if db::CONFIG.jwt_keyring.is_none()
+        && db::CONFIG.secret_key == crate::config::SECRET
+        && !db::CONFIG.dev_mode
+    {
+        return Err(String::from(
+            "SECRET_KEY is the built-in default, set SECRET_KEY or JWT_KEYRING (or DEV_MODE=true for development)",
+        ));
+    }
+    if KEYRING.signing().is_none() {
+        return Err(String::from("no JWT key can sign tokens right now"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_header() {
+        // {"alg":"RS256","kid":"2026-10","typ":"JWT"}
+        let token = "eyJhbGciOiJSUzI1NiIsImtpZCI6IjIwMjYtMTAiLCJ0eXAiOiJKV1QifQ.e30.sig";
+        let header = token_header(token).unwrap();
+        assert_eq!(header["kid"], "2026-10");
+        assert_eq!(header["alg"], "RS256");
+        assert!(token_header("not a token").is_none());
+    }
+
+    #[test]
+    fn test_jwk_round_trip() {
+        let rsa = Rsa::generate(2048).unwrap();
+        let pem = PKey::from_rsa(rsa).unwrap().public_key_to_pem().unwrap();
+        let key = Key {
+            kid: String::from("test"),
+            alg: Alg::Jwt(Algorithm::RS256),
+            signing: None,
+            verifying: pem.clone(),
+            not_before: None,
+            not_after: None,
+        };
+        let jwk = key.to_jwk().unwrap();
+        let (alg, found) = jwk_to_pem(&jwk).unwrap();
+        assert_eq!(alg_name(alg), "RS256");
+        assert_eq!(found, pem);
+
+        let mut wrong = jwk.clone();
+        wrong["alg"] = json!("ES256");
+        assert!(jwk_to_pem(&wrong).is_none());
+    }
+
+    #[test]
+    fn test_eddsa() {
+        let pkey = PKey::generate_ed25519().unwrap();
+        let key = Key {
+            kid: String::from("ed"),
+            alg: Alg::EdDSA,
+            signing: Some(pkey.private_key_to_pem_pkcs8().unwrap()),
+            verifying: pkey.public_key_to_pem().unwrap(),
+            not_before: None,
+            not_after: None,
+        };
+        let exp = Utc::now().timestamp() + 60;
+        let token = encode(
+            json!({ "kid": "ed" }),
+            key.signing_key().unwrap(),
+            &json!({ "id": 7, "exp": exp }),
+            key.alg,
+        )
+        .unwrap();
+        assert_eq!(token_header(&token).unwrap()["alg"], "EdDSA");
+        let payload = decode(&token, key.verifying_key(), key.alg).unwrap();
+        assert_eq!(payload["id"], 7);
+
+        let tampered = format!("{}x", token);
+        assert!(decode(&tampered, key.verifying_key(), key.alg).is_err());
+        let expired = encode(
+            json!({}),
+            key.signing_key().unwrap(),
+            &json!({ "exp": 0 }),
+            key.alg,
+        );
+        assert!(decode(&expired.unwrap(), key.verifying_key(), key.alg).is_err());
+
+        let (alg, pem) = jwk_to_pem(&key.to_jwk().unwrap()).unwrap();
+        assert_eq!(alg, Alg::EdDSA);
+        assert_eq!(pem, key.verifying);
+        // no key material in the logs
+        assert!(!format!("{:?}", key).contains("PRIVATE"));
+    }
+}
@@ -2,22 +2,91 @@
 extern crate diesel;
 
 use log::info;
+use std::time::Duration;
 
 mod auth;
 mod config;
 pub mod db;
+mod diff;
 mod errors;
+mod keys;
+mod mail;
+mod markdown;
 pub mod models;
+mod oidc;
+mod password;
+pub mod policy;
 mod routes;
 pub mod schema;
+mod totp;
 
 pub async fn launch() {
     let sn = db::CONFIG.server_name.to_string();
     info!("{} starting ...", sn);
     info!("Board Mode: {}", db::CONFIG.public_board.to_string());
+    if let Err(err) = keys::check_startup()
+        .and_then(|_| mail::check_startup())
+        .and_then(|_| oidc::check_startup())
+    {
+        log::error!("{}", err);
+        std::process::exit(1);
+    }
+    let conn = db::PG_POOL.clone().get().unwrap();
+    match db::search::set_language(&conn, &db::CONFIG.search_language) {
+        Ok(true) => info!("Search index rebuilt for {}", db::CONFIG.search_language),
+        Ok(false) => {}
+        Err(err) => {
+            log::error!("SEARCH_LANGUAGE {}: {}", db::CONFIG.search_language, err);
+            std::process::exit(1);
+        }
+    }
+    let rendered = db::articles::render_bodies(&conn)
+        .and_then(|articles| Ok(articles + db::comments::render_bodies(&conn)?));
+    match rendered {
+        Ok(0) => {}
+        Ok(count) => info!("{} bodies rendered to html", count),
+        Err(err) => log::error!("render_bodies: {}", err),
+    }
+    tokio::spawn(publish_scheduled());
+    tokio::spawn(flush_views());
     routes::web_routes().await;
 }
 
+/// Publish the scheduled articles once their time has come.
+async fn publish_scheduled() {
+    let mut interval = tokio::time::interval(Duration::from_secs(config::PUBLISH_CHECK_SECONDS));
+    loop {
+        interval.tick().await;
+        let published = tokio::task::spawn_blocking(|| {
+            let conn = db::PG_POOL.clone().get().ok()?;
+            db::articles::publish_due(&conn)
+                .map_err(|err| log::error!("publish_scheduled: {}", err))
+                .ok()
+        })
+        .await;
+        if let Ok(Some(count)) = published {
+            if count > 0 {
+                info!("{} scheduled article(s) published", count);
+            }
+        }
+    }
+}
+
+/// Write the views counted in memory.
+async fn flush_views() {
+    let mut interval = tokio::time::interval(Duration::from_secs(config::VIEWS_FLUSH_SECONDS));
+    loop {
+        interval.tick().await;
+        let _ = tokio::task::spawn_blocking(|| {
+            let conn = db::PG_POOL.clone().get().ok()?;
+            db::views::flush(&conn)
+                .map_err(|err| log::error!("flush_views: {}", err))
+                .ok()
+        })
+        .await;
+    }
+}
+
 // Run test code and output:
 // cargo test -- --nocapture
 #[cfg(test)]
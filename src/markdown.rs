@@ -0,0 +1,63 @@
+//! Article and comment bodies are CommonMark with the GitHub tables, task
+//! lists and strikethrough, rendered here to html that is safe to show as is.
+use ammonia::Builder;
+use lazy_static::lazy_static;
+use pulldown_cmark::{html, Options, Parser};
+use std::borrow::Cow;
+
+lazy_static! {
+    /// ammonia's allowlist, plus the checkboxes of task lists
+    static ref SANITIZER: Builder<'static> = {
+        let mut builder = Builder::default();
+        builder
+            .add_tags(&["input"])
+            .add_tag_attributes("input", &["type", "checked", "disabled"])
+            .add_tag_attributes("code", &["class"])
+            .attribute_filter(|element, attribute, value| match (element, attribute) {
+                // no text fields or buttons, whatever the body asks for
+                ("input", "type") => Some(Cow::Borrowed("checkbox")),
+                _ => Some(Cow::Borrowed(value)),
+            });
+        builder
+    };
+}
+
+pub fn render(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options));
+    SANITIZER.clean(&unsafe_html).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render("# Dragons\n\n*fly*"),
+            "<h1>Dragons</h1>\n<p><em>fly</em></p>\n"
+        );
+        assert_eq!(
+            render("| a | b |\n|---|---|\n| 1 | 2 |")
+                .matches("<td>")
+                .count(),
+            2
+        );
+        assert!(render("- [x] done").contains(r#"<input disabled="" type="checkbox" checked="">"#));
+    }
+
+    #[test]
+    fn test_render_sanitizes() {
+        let html =
+            render("<script>alert(1)</script>\n\n<img src=x onerror=alert(1)> <input type=text>");
+        assert_eq!(html, "\n<p><img src=\"x\"> <input type=\"checkbox\"></p>\n");
+        let html = render("[a](javascript:alert(1)) [b](https://example.com)");
+        assert!(!html.contains("javascript"));
+        assert!(html.contains(r#"<a href="https://example.com" rel="noopener noreferrer">b</a>"#));
+    }
+}
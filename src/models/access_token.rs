@@ -0,0 +1,44 @@
+use crate::config::DATE_FORMAT;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Queryable, Debug)]
+pub struct AccessToken {
+    pub id: i32,
+    pub user: i32,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl AccessToken {
+    pub fn attach(self, token: Option<String>) -> AccessTokenJson {
+        AccessTokenJson {
+            id: self.id,
+            name: self.name,
+            scopes: self.scopes,
+            created_at: self.created_at.format(DATE_FORMAT).to_string(),
+            expires_at: self.expires_at.map(|t| t.format(DATE_FORMAT).to_string()),
+            last_used_at: self.last_used_at.map(|t| t.format(DATE_FORMAT).to_string()),
+            token,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessTokenJson {
+    pub id: i32,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    /// the plain token, only in the answer to its creation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
@@ -1,8 +1,43 @@
 use crate::config::DATE_FORMAT;
+use crate::markdown;
+use crate::models::reaction::Reaction;
 use crate::models::user::PubProfile;
 use crate::models::user::User;
 use chrono::{DateTime, Utc};
 use serde::Serialize;
+use std::str::FromStr;
+
+/// Only published articles are seen by everyone, the others by their authors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Draft,
+    Published,
+    /// published by the server at `published_at`
+    Scheduled,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Draft => "draft",
+            Status::Published => "published",
+            Status::Scheduled => "scheduled",
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "draft" => Ok(Status::Draft),
+            "published" => Ok(Status::Published),
+            "scheduled" => Ok(Status::Scheduled),
+            _ => Err(()),
+        }
+    }
+}
 
 #[derive(Queryable)]
 pub struct Article {
@@ -16,22 +51,47 @@ pub struct Article {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub favorites_count: i32,
+    pub status: String,
+    pub published_at: Option<DateTime<Utc>>,
+    /// rendered with the body, None until then for older rows
+    pub body_html: Option<String>,
+    /// views written so far, see `db::views`
+    pub views_count: i32,
 }
 
 impl Article {
+    pub fn status(&self) -> Status {
+        self.status.parse().unwrap_or(Status::Draft)
+    }
+
+    fn body_html(&self) -> String {
+        self.body_html
+            .clone()
+            .unwrap_or_else(|| markdown::render(&self.body))
+    }
+
     pub fn attach(self, author: User, favorited: bool) -> ArticleJson {
         ArticleJson {
+            body_html: Some(self.body_html()),
             id: self.id,
             slug: self.slug,
             title: self.title,
             description: self.description,
             body: self.body,
             author,
+            authors: Vec::new(),
             tag_list: self.tag_list,
             created_at: self.created_at.format(DATE_FORMAT).to_string(),
             updated_at: self.updated_at.format(DATE_FORMAT).to_string(),
             favorites_count: self.favorites_count,
+            views_count: self.views_count,
             favorited,
+            bookmarked: None,
+            reactions: Vec::new(),
+            status: self.status,
+            published_at: self
+                .published_at
+                .map(|at| at.format(DATE_FORMAT).to_string()),
         }
     }
     pub fn attach_profile(
@@ -41,6 +101,7 @@ impl Article {
         following: bool,
     ) -> ArticleProfileJson {
         ArticleProfileJson {
+            body_html: Some(self.body_html()),
             id: self.id,
             slug: self.slug,
             title: self.title,
@@ -53,11 +114,19 @@ impl Article {
                 following: following,
                 image: author.image,
             },
+            authors: Vec::new(),
             tag_list: self.tag_list,
             created_at: self.created_at.format(DATE_FORMAT).to_string(),
             updated_at: self.updated_at.format(DATE_FORMAT).to_string(),
             favorites_count: self.favorites_count,
+            views_count: self.views_count,
             favorited,
+            bookmarked: None,
+            reactions: Vec::new(),
+            status: self.status,
+            published_at: self
+                .published_at
+                .map(|at| at.format(DATE_FORMAT).to_string()),
         }
     }
 }
@@ -70,12 +139,24 @@ pub struct ArticleJson {
     pub title: String,
     pub description: String,
     pub body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_html: Option<String>,
     pub author: User,
+    /// the author first then the co-authors, filled in by `db::authors`
+    pub authors: Vec<PubProfile>,
     pub tag_list: Vec<String>,
     pub created_at: String,
     pub updated_at: String,
     pub favorites_count: i32,
+    pub views_count: i32,
     pub favorited: bool,
+    /// only there for the user asking, filled in by `db::bookmarks`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bookmarked: Option<bool>,
+    /// filled in by `db::reactions`
+    pub reactions: Vec<Reaction>,
+    pub status: String,
+    pub published_at: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -86,11 +167,23 @@ pub struct ArticleProfileJson {
     pub title: String,
     pub description: String,
     pub body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_html: Option<String>,
     // pub author: User,
     pub author: PubProfile,
+    /// the author first then the co-authors, filled in by `db::authors`
+    pub authors: Vec<PubProfile>,
     pub tag_list: Vec<String>,
     pub created_at: String,
     pub updated_at: String,
     pub favorites_count: i32,
+    pub views_count: i32,
     pub favorited: bool,
+    /// only there for the user asking, filled in by `db::bookmarks`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bookmarked: Option<bool>,
+    /// filled in by `db::reactions`
+    pub reactions: Vec<Reaction>,
+    pub status: String,
+    pub published_at: Option<String>,
 }
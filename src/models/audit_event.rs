@@ -0,0 +1,44 @@
+use crate::config::DATE_FORMAT;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Queryable, Debug)]
+pub struct AuditEvent {
+    pub id: i32,
+    pub actor: Option<i32>,
+    pub actor_name: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub diff: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEvent {
+    pub fn attach(self) -> AuditEventJson {
+        AuditEventJson {
+            id: self.id,
+            actor: self.actor,
+            actor_name: self.actor_name,
+            action: self.action,
+            target: self.target,
+            ip: self.ip,
+            diff: self.diff,
+            created_at: self.created_at.format(DATE_FORMAT).to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEventJson {
+    pub id: i32,
+    pub actor: Option<i32>,
+    pub actor_name: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub diff: Value,
+    pub created_at: String,
+}
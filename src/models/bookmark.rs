@@ -0,0 +1,34 @@
+use crate::config::DATE_FORMAT;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// An article kept to read later, only its user sees it.
+#[derive(Queryable, Debug)]
+pub struct Bookmark {
+    pub user: i32,
+    pub article: i32,
+    pub folder: Option<String>,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Bookmark {
+    /// `article` as listed or as a single article.
+    pub fn attach<A>(self, article: A) -> BookmarkJson<A> {
+        BookmarkJson {
+            article,
+            folder: self.folder,
+            note: self.note,
+            created_at: self.created_at.format(DATE_FORMAT).to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookmarkJson<A> {
+    pub article: A,
+    pub folder: Option<String>,
+    pub note: Option<String>,
+    pub created_at: String,
+}
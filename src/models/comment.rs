@@ -1,4 +1,6 @@
 use crate::config::DATE_FORMAT;
+use crate::markdown;
+use crate::models::reaction::Reaction;
 use crate::models::user::User;
 use chrono::{DateTime, Utc};
 use serde::Serialize;
@@ -11,14 +13,24 @@ pub struct Comment {
     pub author: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// rendered with the body, None until then for older rows
+    pub body_html: Option<String>,
 }
 
 impl Comment {
+    fn body_html(&self) -> String {
+        self.body_html
+            .clone()
+            .unwrap_or_else(|| markdown::render(&self.body))
+    }
+
     pub fn attach(self, author: User) -> CommentJson {
         CommentJson {
+            body_html: Some(self.body_html()),
             id: self.id,
             body: self.body,
             author,
+            reactions: Vec::new(),
             created_at: self.created_at.format(DATE_FORMAT).to_string(),
             updated_at: self.updated_at.format(DATE_FORMAT).to_string(),
         }
@@ -30,7 +42,11 @@ impl Comment {
 pub struct CommentJson {
     pub id: i32,
     pub body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_html: Option<String>,
     pub author: User,
+    /// filled in by `db::reactions`
+    pub reactions: Vec<Reaction>,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -1,3 +1,9 @@
+pub mod access_token;
 pub mod article;
+pub mod audit_event;
+pub mod bookmark;
 pub mod comment;
+pub mod reaction;
+pub mod revision;
+pub mod session;
 pub mod user;
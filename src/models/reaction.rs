@@ -0,0 +1,10 @@
+use serde::Serialize;
+
+/// How many reacted to an article or comment with `emoji`, and whether the
+/// user asking did.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Reaction {
+    pub emoji: String,
+    pub count: i64,
+    pub reacted: bool,
+}
@@ -0,0 +1,63 @@
+use crate::config::DATE_FORMAT;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Queryable, Debug)]
+pub struct Revision {
+    pub id: i32,
+    pub article: i32,
+    pub number: i32,
+    pub editor: Option<i32>,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub tag_list: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Revision {
+    /// The revision as one text, what diffs are made of.
+    pub fn text(&self) -> String {
+        format!(
+            "# {}\n\n{}\n\nTags: {}\n\n{}\n",
+            self.title,
+            self.description,
+            self.tag_list.join(", "),
+            self.body
+        )
+    }
+
+    /// `editor` is the username, None once the user is deleted.
+    pub fn attach(self, editor: Option<String>) -> RevisionJson {
+        RevisionJson {
+            number: self.number,
+            editor,
+            title: self.title,
+            description: self.description,
+            body: Some(self.body),
+            tag_list: self.tag_list,
+            created_at: self.created_at.format(DATE_FORMAT).to_string(),
+        }
+    }
+
+    /// For lists, without the body.
+    pub fn summary(self, editor: Option<String>) -> RevisionJson {
+        RevisionJson {
+            body: None,
+            ..self.attach(editor)
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionJson {
+    pub number: i32,
+    pub editor: Option<String>,
+    pub title: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    pub tag_list: Vec<String>,
+    pub created_at: String,
+}
@@ -0,0 +1,45 @@
+use crate::config::DATE_FORMAT;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Queryable, Debug)]
+pub struct Session {
+    pub id: i32,
+    pub user: i32,
+    pub refresh_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub last_seen_at: DateTime<Utc>,
+    /// the refresh token rotated out last, to notice it being used again
+    pub previous_refresh_hash: Option<String>,
+    /// repeated by writes with the session cookie, None for sessions older than it
+    pub csrf_token: Option<String>,
+}
+
+impl Session {
+    /// `current` is the session of the request, the device the user is looking from.
+    pub fn attach(self, current: i32) -> SessionJson {
+        SessionJson {
+            id: self.id,
+            user_agent: self.user_agent,
+            ip: self.ip,
+            created_at: self.created_at.format(DATE_FORMAT).to_string(),
+            last_seen_at: self.last_seen_at.format(DATE_FORMAT).to_string(),
+            current: self.id == current,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionJson {
+    pub id: i32,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: String,
+    pub last_seen_at: String,
+    pub current: bool,
+}
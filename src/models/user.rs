@@ -1,7 +1,8 @@
 use crate::auth::Auth;
 use crate::config;
+use crate::policy::Role;
 use crate::schema::users;
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::Serialize;
 
 type Url = String;
@@ -16,6 +17,10 @@ pub struct User {
     pub image: Option<Url>,
     #[serde(skip_serializing)]
     pub hash: String,
+    #[serde(skip_serializing)]
+    pub role: String,
+    #[serde(skip_serializing)]
+    pub verified_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Queryable, Serialize)]
@@ -30,7 +35,21 @@ pub struct UserAuth<'a> {
     email: &'a str,
     bio: Option<&'a str>,
     image: Option<&'a str>,
+    role: &'a str,
+    verified: bool,
     token: String,
+    #[serde(rename = "refreshToken", skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+}
+
+impl UserAuth<'_> {
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn refresh_token(&self) -> Option<&str> {
+        self.refresh_token.as_deref()
+    }
 }
 
 #[derive(Serialize)]
@@ -41,6 +60,15 @@ pub struct Profile {
     following: bool,
 }
 
+/// user as listed in the admin api
+#[derive(Serialize)]
+pub struct AdminUser {
+    id: i32,
+    username: String,
+    email: String,
+    role: String,
+}
+
 #[derive(Serialize)]
 pub struct PubProfile {
     pub id: i32,
@@ -51,12 +79,16 @@ pub struct PubProfile {
 }
 
 impl User {
-    pub fn to_user_auth(&self) -> UserAuth {
-        let exp = Utc::now() + Duration::days(config::LOGIN_EXPIRE_DAYS); // TODO: move to config
+    /// `refresh_token` is only given out when the session is opened or rotated.
+    pub fn to_user_auth(&self, sid: i32, refresh_token: Option<String>) -> UserAuth {
+        let exp = Utc::now() + Duration::minutes(config::ACCESS_EXPIRE_MINUTES);
         let token = Auth {
             id: self.id,
             username: self.username.clone(),
             exp: exp.timestamp(),
+            sid,
+            role: self.role(),
+            scopes: None,
         }
         .token();
 
@@ -65,7 +97,23 @@ impl User {
             email: &self.email,
             bio: self.bio.as_ref().map(String::as_str),
             image: self.image.as_ref().map(String::as_str),
+            role: &self.role,
+            verified: self.verified_at.is_some(),
             token,
+            refresh_token,
+        }
+    }
+
+    pub fn role(&self) -> Role {
+        self.role.parse().unwrap_or_default()
+    }
+
+    pub fn to_admin_user(self) -> AdminUser {
+        AdminUser {
+            id: self.id,
+            username: self.username,
+            email: self.email,
+            role: self.role,
         }
     }
 
@@ -0,0 +1,286 @@
+//! Login through an OpenID Connect provider, authorization code flow with PKCE.
+//!
+//! The provider is found by discovery below `OIDC_ISSUER`, so the company IdP
+//! and a local mock server (e.g. `http://localhost:8080/default`) work alike.
+//! Logins in progress are kept in memory, they don't survive a restart.
+use crate::config;
+use crate::db;
+use crate::keys;
+use chrono::Utc;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Deserialize, Debug, Clone)]
+struct Provider {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+struct Pending {
+    verifier: String,
+    nonce: String,
+    created_at: i64,
+}
+
+/// The account at the provider, as told by its ID token.
+#[derive(Debug)]
+pub struct Identity {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub username: Option<String>,
+}
+
+lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::new();
+    static ref PROVIDER: Mutex<Option<Provider>> = Mutex::new(None);
+    static ref PENDING: Mutex<HashMap<String, Pending>> = Mutex::new(HashMap::new());
+}
+
+pub fn is_enabled() -> bool {
+    db::CONFIG.oidc_issuer.is_some()
+}
+
+/// The callback logs in with the session cookies, it can't without them.
+pub fn check_startup() -> Result<(), String> {
+    if is_enabled() && !db::CONFIG.cookie_sessions {
+        return Err(String::from("OIDC_ISSUER needs COOKIE_SESSIONS=true"));
+    }
+    Ok(())
+}
+
+async fn get_json(url: &str) -> Result<Value, String> {
+    CLIENT
+        .get(url)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|err| format!("{}: {}", url, err))?
+        .json::<Value>()
+        .await
+        .map_err(|err| format!("{}: {}", url, err))
+}
+
+/// The discovery document, fetched once.
+async fn provider() -> Result<Provider, String> {
+    if let Some(provider) = PROVIDER.lock().unwrap().as_ref() {
+        return Ok(provider.clone());
+    }
+    let issuer = db::CONFIG
+        .oidc_issuer
+        .as_ref()
+        .ok_or("OIDC_ISSUER is not set")?;
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    );
+    let provider: Provider =
+        serde_json::from_value(get_json(&url).await?).map_err(|err| format!("{}: {}", url, err))?;
+    *PROVIDER.lock().unwrap() = Some(provider.clone());
+    Ok(provider)
+}
+
+/// S256 code challenge of a PKCE verifier (RFC 7636).
+fn code_challenge(verifier: &str) -> String {
+    base64::encode_config(Sha256::digest(verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+/// Start a login: remember a PKCE verifier and a nonce under a new state,
+/// return the url of the provider to send the browser to and the state.
+pub async fn authorization_url() -> Result<(String, String), String> {
+    let provider = provider().await?;
+    let state = db::generate_secret();
+    let pending = Pending {
+        verifier: db::generate_secret(),
+        nonce: db::generate_secret(),
+        created_at: Utc::now().timestamp(),
+    };
+    let url = reqwest::Url::parse_with_params(
+        &provider.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", &db::CONFIG.oidc_client_id),
+            ("redirect_uri", &db::CONFIG.oidc_redirect_url),
+            ("scope", "openid email profile"),
+            ("state", &state),
+            ("nonce", &pending.nonce),
+            ("code_challenge", &code_challenge(&pending.verifier)),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|err| format!("{}: {}", provider.authorization_endpoint, err))?;
+
+    let mut logins = PENDING.lock().unwrap();
+    let expired = Utc::now().timestamp() - config::OIDC_LOGIN_EXPIRE_MINUTES * 60;
+    logins.retain(|_, p| p.created_at > expired);
+    logins.insert(state.clone(), pending);
+    Ok((url.into(), state))
+}
+
+/// Finish a login: trade the code the provider sent back for the ID token.
+pub async fn exchange(code: &str, state: &str) -> Result<Identity, String> {
+    let pending = PENDING
+        .lock()
+        .unwrap()
+        .remove(state)
+        .ok_or("unknown login state")?;
+    if pending.created_at < Utc::now().timestamp() - config::OIDC_LOGIN_EXPIRE_MINUTES * 60 {
+        return Err(String::from("login expired"));
+    }
+
+    let provider = provider().await?;
+    let response = CLIENT
+        .post(&provider.token_endpoint)
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &db::CONFIG.oidc_redirect_url),
+            ("client_id", &db::CONFIG.oidc_client_id),
+            ("client_secret", &db::CONFIG.oidc_client_secret),
+            ("code_verifier", &pending.verifier),
+        ])
+        .send()
+        .await
+        .map_err(|err| format!("{}: {}", provider.token_endpoint, err))?;
+    let status = response.status();
+    let tokens = response
+        .json::<Value>()
+        .await
+        .map_err(|err| format!("{}: {}", provider.token_endpoint, err))?;
+    if !status.is_success() {
+        return Err(format!(
+            "{}: {} {}",
+            provider.token_endpoint, status, tokens
+        ));
+    }
+    let id_token = tokens["id_token"]
+        .as_str()
+        .ok_or("no id_token in the response")?;
+
+    let jwks = get_json(&provider.jwks_uri).await?;
+    check_id_token(
+        id_token,
+        &jwks,
+        &provider.issuer,
+        &db::CONFIG.oidc_client_id,
+        &pending.nonce,
+    )
+}
+
+/// Verify the signature and the claims of an ID token (OpenID Connect Core 3.1.3.7).
+fn check_id_token(
+    token: &str,
+    jwks: &Value,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+) -> Result<Identity, String> {
+    let kid = keys::token_header(token).ok_or("malformed id_token")?["kid"].clone();
+    let jwk = jwks["keys"]
+        .as_array()
+        .and_then(|keys| keys.iter().find(|k| kid.is_null() || k["kid"] == kid))
+        .ok_or("no key of the provider matches the id_token")?;
+    let (alg, pem) = keys::jwk_to_pem(jwk).ok_or("unsupported key in the provider's JWKS")?;
+    let claims = keys::decode(token, &pem, alg).map_err(|err| format!("id_token: {}", err))?;
+
+    let audience_ok = match &claims["aud"] {
+        Value::String(aud) => aud == client_id,
+        Value::Array(auds) => auds.iter().any(|aud| aud == client_id),
+        _ => false,
+    };
+    if claims["iss"].as_str() != Some(issuer) {
+        return Err(String::from("id_token of another issuer"));
+    }
+    if !audience_ok {
+        return Err(String::from("id_token for another client"));
+    }
+    if claims["nonce"].as_str() != Some(nonce) {
+        return Err(String::from("id_token nonce doesn't match"));
+    }
+    if claims["exp"]
+        .as_i64()
+        .is_none_or(|exp| exp < Utc::now().timestamp())
+    {
+        return Err(String::from("id_token expired"));
+    }
+
+    Ok(Identity {
+        issuer: issuer.to_string(),
+        subject: claims["sub"]
+            .as_str()
+            .ok_or("id_token without sub")?
+            .to_string(),
+        email: claims["email"].as_str().map(String::from),
+        email_verified: claims["email_verified"].as_bool().unwrap_or(false),
+        username: claims["preferred_username"]
+            .as_str()
+            .or(claims["name"].as_str())
+            .map(String::from),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::pkey::{PKey, Private};
+    use serde_json::json;
+
+    const ISSUER: &str = "https://id.example.com";
+    const CLIENT_ID: &str = "realworld";
+    const NONCE: &str = "n-0S6_WzA2Mj";
+
+    fn jwks(pkey: &PKey<Private>) -> Value {
+        let x = base64::encode_config(pkey.raw_public_key().unwrap(), base64::URL_SAFE_NO_PAD);
+        json!({ "keys": [{ "kty": "OKP", "crv": "Ed25519", "alg": "EdDSA", "kid": "k1", "x": x }] })
+    }
+
+    fn id_token(pkey: &PKey<Private>, claims: Value) -> String {
+        let mut payload = json!({
+            "iss": ISSUER,
+            "aud": CLIENT_ID,
+            "sub": "248289761001",
+            "nonce": NONCE,
+            "exp": Utc::now().timestamp() + 60,
+        });
+        for (name, value) in claims.as_object().unwrap() {
+            payload[name] = value.clone();
+        }
+        let pem = pkey.private_key_to_pem_pkcs8().unwrap();
+        keys::encode(json!({ "kid": "k1" }), &pem, &payload, keys::Alg::EdDSA).unwrap()
+    }
+
+    #[test]
+    fn test_check_id_token() {
+        let pkey = PKey::generate_ed25519().unwrap();
+        let jwks = jwks(&pkey);
+        let check = |token: &str| check_id_token(token, &jwks, ISSUER, CLIENT_ID, NONCE);
+
+        let identity = check(&id_token(&pkey, json!({ "aud": ["other", CLIENT_ID] }))).unwrap();
+        assert_eq!(identity.issuer, ISSUER);
+        assert_eq!(identity.subject, "248289761001");
+
+        assert!(check(&id_token(&pkey, json!({ "iss": "https://evil.example" }))).is_err());
+        assert!(check(&id_token(&pkey, json!({ "aud": "other" }))).is_err());
+        assert!(check(&id_token(&pkey, json!({ "nonce": "replayed" }))).is_err());
+        let expired = Utc::now().timestamp() - 60;
+        assert!(check(&id_token(&pkey, json!({ "exp": expired }))).is_err());
+        let other = PKey::generate_ed25519().unwrap();
+        assert!(check(&id_token(&other, json!({}))).is_err());
+    }
+
+    #[test]
+    fn test_code_challenge() {
+        // base64url of the sha256, without padding
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r7wW1gFWFOEjXk"),
+            "bwWFMyPfdG9qreDhH2lmftFx_dFeLDalzcT1gb_j68g"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Password hashing and strength rules.
+//!
+//! A hash starts with its format (`$argon2id$...`, `$2b$...`), so the bcrypt
+//! hashes of older accounts keep working and are replaced with the current
+//! policy the next time their owner logs in.
+
+use crate::db;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use lazy_static::lazy_static;
+use rand::{thread_rng, Rng};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::str::FromStr;
+use validator::ValidationError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scheme {
+    Argon2id,
+    Bcrypt,
+}
+
+impl FromStr for Scheme {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "argon2id" => Ok(Scheme::Argon2id),
+            "bcrypt" => Ok(Scheme::Bcrypt),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How new passwords are hashed, from `PASSWORD_HASH` and the cost settings.
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub scheme: Scheme,
+    /// memory of argon2 in KiB
+    pub argon2_memory: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    pub bcrypt_cost: u32,
+}
+
+impl Policy {
+    fn argon2(&self) -> Argon2<'static> {
+        let params = Params::new(
+            self.argon2_memory,
+            self.argon2_iterations,
+            self.argon2_parallelism,
+            None,
+        )
+        .expect("Invalid argon2 parameters");
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+    }
+
+    pub fn hash(&self, password: &str) -> String {
+        match self.scheme {
+            Scheme::Argon2id => {
+                let salt: [u8; 16] = thread_rng().gen();
+                let salt = SaltString::encode_b64(&salt).expect("Error encoding salt");
+                self.argon2()
+                    .hash_password(password.as_bytes(), &salt)
+                    .expect("Error hashing password")
+                    .to_string()
+            }
+            Scheme::Bcrypt => {
+                bcrypt::hash(password.as_bytes(), self.bcrypt_cost).expect("Error hashing password")
+            }
+        }
+    }
+
+    /// True when `hash` was made with another scheme or other costs.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        match self.scheme {
+            Scheme::Argon2id => {
+                let hash = match PasswordHash::new(hash) {
+                    Ok(hash) => hash,
+                    Err(_) => return true,
+                };
+                let params = match Params::try_from(&hash) {
+                    Ok(params) => params,
+                    Err(_) => return true,
+                };
+                hash.algorithm != argon2::ARGON2ID_IDENT
+                    || params.m_cost() != self.argon2_memory
+                    || params.t_cost() != self.argon2_iterations
+                    || params.p_cost() != self.argon2_parallelism
+            }
+            Scheme::Bcrypt => bcrypt_cost(hash) != Some(self.bcrypt_cost),
+        }
+    }
+}
+
+/// `$2b$12$...` has the cost 12
+fn bcrypt_cost(hash: &str) -> Option<u32> {
+    if !hash.starts_with("$2") {
+        return None;
+    }
+    hash.split('$').nth(2)?.parse().ok()
+}
+
+/// Hash with the configured policy.
+pub fn hash(password: &str) -> String {
+    db::CONFIG.password_policy.hash(password)
+}
+
+pub fn needs_rehash(hash: &str) -> bool {
+    db::CONFIG.password_policy.needs_rehash(hash)
+}
+
+/// Check a password against a hash of any supported format.
+pub fn verify(password: &str, hash: &str) -> bool {
+    if hash.starts_with("$argon2") {
+        // the parameters are read from the hash itself
+        PasswordHash::new(hash)
+            .map(|hash| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &hash)
+                    .is_ok()
+            })
+            .unwrap_or(false)
+    } else {
+        bcrypt::verify(password.as_bytes(), hash).unwrap_or(false)
+    }
+}
+
+lazy_static! {
+    /// One password per line, compared case-insensitively.
+    static ref BREACHED: HashSet<String> = match &db::CONFIG.breached_passwords {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(list) => list.lines().map(|line| line.trim().to_lowercase()).collect(),
+            Err(err) => {
+                log::error!("cannot read breached passwords from {}: {}", path, err);
+                HashSet::new()
+            }
+        },
+        None => HashSet::new(),
+    };
+}
+
+/// Validator for new passwords: long enough and not on the breached list.
+pub fn validate_strength(password: &str) -> Result<(), ValidationError> {
+    if password.chars().count() < db::CONFIG.password_min_length {
+        return Err(ValidationError::new("is too short"));
+    }
+    if BREACHED.contains(&password.to_lowercase()) {
+        return Err(ValidationError::new("is a known breached password"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(iterations: u32) -> Policy {
+        Policy {
+            scheme: Scheme::Argon2id,
+            argon2_memory: 64,
+            argon2_iterations: iterations,
+            argon2_parallelism: 1,
+            bcrypt_cost: 4,
+        }
+    }
+
+    #[test]
+    fn test_argon2_hash() {
+        let hash = policy(1).hash("correct horse");
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify("correct horse", &hash));
+        assert!(!verify("battery staple", &hash));
+
+        assert!(!policy(1).needs_rehash(&hash));
+        assert!(policy(2).needs_rehash(&hash));
+        let bcrypt_hash = "$2b$12$R9h/cIPz0gi.URNNX3kh2OPST9/PgBkqquzi.Ss7KIUgO2t0jWMUW";
+        assert!(policy(1).needs_rehash(bcrypt_hash));
+        assert_eq!(bcrypt_cost(bcrypt_hash), Some(12));
+    }
+}
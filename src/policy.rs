@@ -0,0 +1,146 @@
+//! Roles and what they are allowed to do.
+use crate::auth::Auth;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::DeleteAnyArticle | Permission::DeleteAnyComment => *self >= Role::Moderator,
+            Permission::ManageUsers | Permission::ManageTags | Permission::ViewAuditLog => {
+                *self == Role::Admin
+            }
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    DeleteAnyArticle,
+    DeleteAnyComment,
+    ManageUsers,
+    /// rename, merge and block tags
+    ManageTags,
+    ViewAuditLog,
+}
+
+/// What a personal access token may be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "read")]
+    Read,
+    #[serde(rename = "articles:write")]
+    ArticlesWrite,
+    #[serde(rename = "comments:write")]
+    CommentsWrite,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::ArticlesWrite => "articles:write",
+            Scope::CommentsWrite => "comments:write",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Scope::Read),
+            "articles:write" => Ok(Scope::ArticlesWrite),
+            "comments:write" => Ok(Scope::CommentsWrite),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Co-authors may not delete the article, only its author.
+pub fn can_delete_article(auth: &Auth, author: i32) -> bool {
+    auth.id == author || auth.role.can(Permission::DeleteAnyArticle)
+}
+
+/// The author edits an article, a co-author once they accepted the invitation.
+pub fn can_edit_article(user_id: i32, author: i32, accepted_co_author: bool) -> bool {
+    user_id == author || accepted_co_author
+}
+
+/// Authors may clean up the comments under their own articles.
+pub fn can_delete_comment(auth: &Auth, comment_author: i32, article_author: i32) -> bool {
+    auth.id == comment_author
+        || auth.id == article_author
+        || auth.role.can(Permission::DeleteAnyComment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_permissions() {
+        assert!(!Role::User.can(Permission::DeleteAnyArticle));
+        assert!(Role::Moderator.can(Permission::DeleteAnyComment));
+        assert!(!Role::Moderator.can(Permission::ManageUsers));
+        assert!(Role::Admin.can(Permission::ManageUsers));
+        assert!(!Role::Moderator.can(Permission::ViewAuditLog));
+        assert!(!Role::Moderator.can(Permission::ManageTags));
+        assert_eq!("moderator".parse::<Role>(), Ok(Role::Moderator));
+        assert!("root".parse::<Role>().is_err());
+        assert_eq!("articles:write".parse::<Scope>(), Ok(Scope::ArticlesWrite));
+        assert_eq!(Scope::CommentsWrite.as_str(), "comments:write");
+    }
+
+    #[test]
+    fn test_article_authors() {
+        assert!(can_edit_article(1, 1, false));
+        assert!(can_edit_article(2, 1, true));
+        // an invitation alone isn't enough
+        assert!(!can_edit_article(2, 1, false));
+
+        let auth = |id, role| Auth {
+            exp: 0,
+            id,
+            username: format!("user{}", id),
+            sid: 0,
+            role,
+            scopes: None,
+        };
+        assert!(can_delete_article(&auth(1, Role::User), 1));
+        // co-authors edit but don't delete
+        assert!(!can_delete_article(&auth(2, Role::User), 1));
+        assert!(can_delete_article(&auth(2, Role::Moderator), 1));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::auth::Auth;
+use crate::db;
+use crate::errors::{ApiError, FieldValidator};
+use crate::policy::Scope;
+use crate::routes;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_list_tokens(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "user" / "tokens"))
+        .and(routes::extract_auth_head())
+        .and_then(handler_list_tokens)
+}
+pub async fn handler_list_tokens(auth: Auth) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_list_tokens: {:?}", auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let tokens: Vec<_> = db::access_tokens::list(&conn, auth.id)
+        .into_iter()
+        .map(|t| t.attach(None))
+        .collect();
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({ "tokens": tokens })),
+        StatusCode::OK,
+    ))
+}
+
+// --------------------------------------------------------------------------------------------------------
+#[derive(Deserialize, Debug)]
+pub struct NewToken {
+    token: NewTokenData,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NewTokenData {
+    name: Option<String>,
+    scopes: Option<Vec<String>>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+pub fn route_create_token(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("api" / "user" / "tokens"))
+        .and(warp::body::json())
+        .and(routes::extract_auth_head())
+        .and_then(handler_create_token)
+}
+pub async fn handler_create_token(
+    new_token: NewToken,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_create_token: {:?} {:?}", new_token, auth);
+    let new_token = new_token.token;
+
+    let mut extractor = FieldValidator::default();
+    let name = extractor.extract("name", new_token.name);
+    let scopes = extractor.extract("scopes", new_token.scopes);
+    extractor.check()?;
+    let scopes: Vec<Scope> = match scopes.iter().map(|s| s.parse()).collect() {
+        Ok(scopes) => scopes,
+        Err(_) => return Err(ApiError::validation("scopes", "is invalid")),
+    };
+    if scopes.is_empty() {
+        return Err(ApiError::validation("scopes", "can't be blank"));
+    }
+    if new_token.expires_at.is_some_and(|t| t <= Utc::now()) {
+        return Err(ApiError::validation("expiresAt", "is in the past"));
+    }
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let (access_token, token) =
+        db::access_tokens::create(&conn, auth.id, &name, &scopes, new_token.expires_at);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({ "token": access_token.attach(Some(token)) })),
+        StatusCode::OK,
+    ))
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_revoke_token(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::delete()
+        .and(warp::path!("api" / "user" / "tokens" / i32))
+        .and(routes::extract_auth_head())
+        .and_then(handler_revoke_token)
+}
+pub async fn handler_revoke_token(id: i32, auth: Auth) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_revoke_token: {} {:?}", id, auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    if db::access_tokens::revoke(&conn, id, auth.id) {
+        Ok(warp::reply::with_status(
+            warp::reply::json(&json!({})),
+            StatusCode::OK,
+        ))
+    } else {
+        Err(ApiError::not_found("token"))
+    }
+}
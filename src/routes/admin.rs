@@ -0,0 +1,357 @@
+use crate::auth::Auth;
+use crate::config;
+use crate::db;
+use crate::db::audit::{self, Actor, FindEvents};
+use crate::db::sessions::Device;
+use crate::errors::{ApiError, FieldName, FieldValidator};
+use crate::policy::{Permission, Role};
+use crate::routes;
+use serde::Deserialize;
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+const DEFAULT_LIMIT: i64 = 20;
+
+#[derive(Deserialize, Debug)]
+pub struct ListUsers {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+pub fn route_list_users() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::get()
+        .and(warp::path!("api" / "admin" / "users"))
+        .and(warp::query::<ListUsers>())
+        .and(routes::require_permission(Permission::ManageUsers))
+        .and_then(handler_list_users)
+}
+pub async fn handler_list_users(
+    params: ListUsers,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_list_users: {:?} {:?}", params, auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let (users, count) = db::users::list(
+        &conn,
+        params.offset.unwrap_or(0),
+        params.limit.unwrap_or(DEFAULT_LIMIT),
+    );
+    let users: Vec<_> = users.into_iter().map(|u| u.to_admin_user()).collect();
+    let tmpjson = json!({ "users": users, "usersCount": count });
+    Ok(warp::reply::json(&tmpjson))
+}
+
+// --------------------------------------------------------------------------------------------------------
+#[derive(Deserialize, Debug)]
+pub struct UpdateUser {
+    user: UpdateUserData,
+}
+
+#[derive(Deserialize, Debug)]
+struct UpdateUserData {
+    role: Option<String>,
+}
+
+pub fn route_update_user(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::put()
+        .and(warp::path!("api" / "admin" / "users" / i32))
+        .and(warp::body::json())
+        .and(routes::require_permission(Permission::ManageUsers))
+        .and(routes::extract_device())
+        .and_then(handler_update_user)
+}
+pub async fn handler_update_user(
+    id: i32,
+    user: UpdateUser,
+    auth: Auth,
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_update_user: {} {:?}", id, user);
+
+    let mut extractor = FieldValidator::default();
+    let role = extractor.extract("role", user.user.role);
+    extractor.check()?;
+    let role = match role.parse::<Role>() {
+        Ok(role) => role,
+        Err(_) => return Err(ApiError::validation("role", "is invalid")),
+    };
+    // an admin demoting themselves could leave nobody to manage users
+    if id == auth.id {
+        return Err(ApiError::forbidden("user", "can't change own role"));
+    }
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let before = db::users::find(&conn, id);
+    match db::users::set_role(&conn, id, role) {
+        Some(user) => {
+            let old_role = before.map(|u| u.role);
+            db::audit::record(
+                &conn,
+                &Actor::from_auth(&auth, device.ip),
+                audit::USER_ROLE,
+                Some(&format!("user:{}", id)),
+                audit::diff(&json!({ "role": old_role }), &json!({ "role": user.role })),
+            );
+            let tmpjson = json!({ "user": user.to_admin_user() });
+            Ok(warp::reply::with_status(
+                warp::reply::json(&tmpjson),
+                StatusCode::OK,
+            ))
+        }
+        None => Err(ApiError::not_found("user")),
+    }
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_delete_user(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::delete()
+        .and(warp::path!("api" / "admin" / "users" / i32))
+        .and(routes::require_permission(Permission::ManageUsers))
+        .and(routes::extract_device())
+        .and_then(handler_delete_user)
+}
+pub async fn handler_delete_user(
+    id: i32,
+    auth: Auth,
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_delete_user: {} {:?}", id, auth);
+    if id == auth.id {
+        return Err(ApiError::forbidden("user", "can't delete itself"));
+    }
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let before = db::users::find(&conn, id);
+    match db::users::delete(&conn, id) {
+        Some(count) if count > 0 => {
+            let before = before.map(|u| json!({ "username": u.username, "email": u.email }));
+            db::audit::record(
+                &conn,
+                &Actor::from_auth(&auth, device.ip),
+                audit::USER_DELETE,
+                Some(&format!("user:{}", id)),
+                audit::diff(&before.unwrap_or_default(), &json!({})),
+            );
+            Ok(StatusCode::OK)
+        }
+        _ => Err(ApiError::not_found("user")),
+    }
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_audit_events(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "admin" / "audit"))
+        .and(warp::query::<FindEvents>())
+        .and(routes::require_permission(Permission::ViewAuditLog))
+        .and_then(handler_audit_events)
+}
+pub async fn handler_audit_events(
+    params: FindEvents,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_audit_events: {:?} {:?}", params, auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let (events, count) = db::audit::find(&conn, &params);
+    let events: Vec<_> = events.into_iter().map(|e| e.attach()).collect();
+    let tmpjson = json!({ "events": events, "eventsCount": count });
+    Ok(warp::reply::json(&tmpjson))
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_list_tags() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::get()
+        .and(warp::path!("api" / "admin" / "tags"))
+        .and(routes::require_permission(Permission::ManageTags))
+        .and_then(handler_list_tags)
+}
+pub async fn handler_list_tags(auth: Auth) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_list_tags: {:?}", auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let tmpjson = json!({ "tags": db::tags::list(&conn) });
+    Ok(warp::reply::json(&tmpjson))
+}
+
+/// The normalized tag of a path segment.
+fn tag_param(segment: &str) -> Result<String, Rejection> {
+    routes::percent_decode(segment)
+        .map(|tag| db::tags::normalize(&tag))
+        .filter(|tag| !tag.is_empty())
+        .ok_or_else(|| ApiError::validation("tag", "is invalid"))
+}
+
+/// A new name for a tag, normalized like the tags of articles.
+fn tag_name(name: Option<String>, field: FieldName) -> Result<String, Rejection> {
+    let mut extractor = FieldValidator::default();
+    let name = extractor.extract(
+        field,
+        name.map(|name| db::tags::normalize(&name))
+            .filter(|name| !name.is_empty()),
+    );
+    extractor.check()?;
+    if name.chars().count() > config::MAX_TAG_LENGTH {
+        return Err(ApiError::validation(field, "is too long"));
+    }
+    Ok(name)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateTag {
+    tag: UpdateTagData,
+}
+
+#[derive(Deserialize, Debug)]
+struct UpdateTagData {
+    name: Option<String>,
+}
+
+pub fn route_rename_tag() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::put()
+        .and(warp::path!("api" / "admin" / "tags" / String))
+        .and(warp::body::json())
+        .and(routes::require_permission(Permission::ManageTags))
+        .and(routes::extract_device())
+        .and_then(handler_rename_tag)
+}
+pub async fn handler_rename_tag(
+    name: String,
+    tag: UpdateTag,
+    auth: Auth,
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_rename_tag: {} {:?}", name, tag);
+    let name = tag_param(&name)?;
+    let new_name = tag_name(tag.tag.name, "name")?;
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    db::tags::find(&conn, &name).ok_or_else(|| ApiError::not_found("tag"))?;
+    // merging is its own action, so a typo can't merge two tags
+    if db::tags::find(&conn, &new_name).is_some() {
+        return Err(ApiError::conflict(
+            "name",
+            "is taken, merge the tags instead",
+        ));
+    }
+    let changed = db::tags::rename(&conn, &name, &new_name).map_err(|err| {
+        log::error!("handler_rename_tag: {}", err);
+        ApiError::internal()
+    })?;
+    db::audit::record(
+        &conn,
+        &Actor::from_auth(&auth, device.ip),
+        audit::TAG_RENAME,
+        Some(&format!("tag:{}", name)),
+        audit::diff(&json!({ "name": name }), &json!({ "name": new_name })),
+    );
+    let tmpjson = json!({ "tag": new_name, "articlesCount": changed });
+    Ok(warp::reply::json(&tmpjson))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MergeTag {
+    tag: MergeTagData,
+}
+
+#[derive(Deserialize, Debug)]
+struct MergeTagData {
+    into: Option<String>,
+}
+
+pub fn route_merge_tag() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::post()
+        .and(warp::path!("api" / "admin" / "tags" / String / "merge"))
+        .and(warp::body::json())
+        .and(routes::require_permission(Permission::ManageTags))
+        .and(routes::extract_device())
+        .and_then(handler_merge_tag)
+}
+pub async fn handler_merge_tag(
+    name: String,
+    merge: MergeTag,
+    auth: Auth,
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_merge_tag: {} {:?}", name, merge);
+    let name = tag_param(&name)?;
+    let into = tag_name(merge.tag.into, "into")?;
+    if into == name {
+        return Err(ApiError::validation("into", "is the same tag"));
+    }
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    db::tags::find(&conn, &name).ok_or_else(|| ApiError::not_found("tag"))?;
+    if db::tags::find(&conn, &into) == Some(true) {
+        return Err(ApiError::validation("into", "is blocked"));
+    }
+    let changed = db::tags::merge(&conn, &name, &into).map_err(|err| {
+        log::error!("handler_merge_tag: {}", err);
+        ApiError::internal()
+    })?;
+    db::audit::record(
+        &conn,
+        &Actor::from_auth(&auth, device.ip),
+        audit::TAG_MERGE,
+        Some(&format!("tag:{}", name)),
+        audit::diff(&json!({ "name": name }), &json!({ "name": into })),
+    );
+    let tmpjson = json!({ "tag": into, "articlesCount": changed });
+    Ok(warp::reply::json(&tmpjson))
+}
+
+pub fn route_block_tag() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::post()
+        .and(warp::path!("api" / "admin" / "tags" / String / "block"))
+        .map(|name| (name, true))
+        .or(warp::delete()
+            .and(warp::path!("api" / "admin" / "tags" / String / "block"))
+            .map(|name| (name, false)))
+        .unify()
+        .and(routes::require_permission(Permission::ManageTags))
+        .and(routes::extract_device())
+        .and_then(handler_block_tag)
+}
+/// Blocking a tag that nobody used yet keeps it from being used.
+pub async fn handler_block_tag(
+    (name, blocked): (String, bool),
+    auth: Auth,
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_block_tag: {} {}", name, blocked);
+    let name = tag_param(&name)?;
+    if name.chars().count() > config::MAX_TAG_LENGTH {
+        return Err(ApiError::validation("tag", "is too long"));
+    }
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let before = db::tags::find(&conn, &name);
+    let changed = db::tags::set_blocked(&conn, &name, blocked).map_err(|err| {
+        log::error!("handler_block_tag: {}", err);
+        ApiError::internal()
+    })?;
+    if before != Some(blocked) {
+        db::audit::record(
+            &conn,
+            &Actor::from_auth(&auth, device.ip),
+            if blocked {
+                audit::TAG_BLOCK
+            } else {
+                audit::TAG_UNBLOCK
+            },
+            Some(&format!("tag:{}", name)),
+            audit::diff(
+                &json!({ "blocked": before.unwrap_or(false) }),
+                &json!({ "blocked": blocked }),
+            ),
+        );
+    }
+    let tmpjson = json!({ "tag": name, "blocked": blocked, "articlesCount": changed });
+    Ok(warp::reply::json(&tmpjson))
+}
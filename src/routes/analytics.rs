@@ -0,0 +1,68 @@
+use crate::auth::Auth;
+use crate::config;
+use crate::db;
+use crate::errors::ApiError;
+use crate::policy::Scope;
+use crate::routes;
+use chrono::{Duration, NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use warp::{Filter, Rejection};
+
+/// Days as `YYYY-MM-DD` in UTC, both included.
+#[derive(Deserialize, Debug)]
+pub struct AnalyticsRange {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+fn parse_day(field: &'static str, day: Option<&str>) -> Result<Option<NaiveDate>, Rejection> {
+    day.map(|day| {
+        NaiveDate::parse_from_str(day, "%Y-%m-%d")
+            .map_err(|_| ApiError::validation(field, "is not a date"))
+    })
+    .transpose()
+}
+
+/// Views, favorites and comments of the user's articles, the last
+/// `ANALYTICS_DAYS` days by default.
+pub fn route_get_analytics(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "user" / "analytics"))
+        .and(warp::query::<AnalyticsRange>())
+        .and(routes::extract_scoped_auth(Scope::Read))
+        .and_then(handler_get_analytics)
+}
+pub async fn handler_get_analytics(
+    range: AnalyticsRange,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_get_analytics: {:?} {:?}", range, auth);
+    let to = parse_day("to", range.to.as_deref())?.unwrap_or_else(|| Utc::now().date_naive());
+    let from = parse_day("from", range.from.as_deref())?
+        .unwrap_or_else(|| to - Duration::days(config::ANALYTICS_DAYS - 1));
+    if from > to {
+        return Err(ApiError::validation("from", "is after to"));
+    }
+    if (to - from).num_days() >= config::ANALYTICS_MAX_DAYS {
+        return Err(ApiError::validation("from", "is too far from to"));
+    }
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let articles = db::views::analytics(&conn, auth.id, from, to);
+    let (views, favorites, comments) = articles.iter().fold((0, 0, 0), |sum, stats| {
+        (
+            sum.0 + stats.views,
+            sum.1 + stats.favorites,
+            sum.2 + stats.comments,
+        )
+    });
+    let tmpjson = json!({
+        "from": from.to_string(),
+        "to": to.to_string(),
+        "articles": articles,
+        "totals": { "views": views, "favorites": favorites, "comments": comments },
+    });
+    Ok(warp::reply::json(&tmpjson))
+}
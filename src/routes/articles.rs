@@ -1,14 +1,19 @@
 use crate::auth::Auth;
 use crate::db;
 use crate::db::articles::{FeedArticles, FindArticles};
-use crate::errors::{Errors, FieldValidator};
+use crate::db::audit::{self, Actor};
+use crate::db::comments::FindComments;
+use crate::db::sessions::Device;
+use crate::errors::{ApiError, FieldValidator};
+use crate::models::article::Status;
+use crate::policy::Scope;
 use crate::routes;
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use serde_json::json;
-use std::convert::Infallible;
 use validator::Validate;
 use warp::http::StatusCode;
-use warp::Filter;
+use warp::{Filter, Rejection, Reply};
 
 #[derive(Deserialize, Debug)]
 pub struct NewArticle {
@@ -25,43 +30,47 @@ pub struct NewArticleData {
     body: Option<String>,
     #[serde(rename = "tagList")]
     tag_list: Vec<String>,
+    /// `draft` or `published`, the default
+    status: Option<String>,
 }
 pub fn route_new_article(
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("api" / "articles")
         .and(warp::post())
         .and(warp::body::json())
-        .and(routes::extract_auth_head())
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
         .and_then(handler_new_article)
 }
 pub async fn handler_new_article(
     new_article: NewArticle,
     auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_new_article: {:?}", new_article);
-    if auth.exp == 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    }
     let new_article = new_article.article;
 
     let mut extractor = FieldValidator::validate(&new_article);
     let title = extractor.extract("title", new_article.title);
     let description = extractor.extract("description", new_article.description);
     let body = extractor.extract("body", new_article.body);
-    match extractor.check() {
-        Ok(_) => (),
-        Err(err) => return err.respond_to(),
+    extractor.check()?;
+    let status = match new_article.status.as_ref().map(|s| s.parse()) {
+        None => Status::Published,
+        Some(Ok(status)) if status != Status::Scheduled => status,
+        Some(_) => return Err(ApiError::validation("status", "is invalid")),
     };
 
     let conn = db::PG_POOL.clone().get().unwrap();
+    routes::check_verified(&conn, &auth)?;
+    let tag_list = db::tags::check(&conn, &new_article.tag_list)
+        .map_err(|code| ApiError::validation("tagList", code))?;
     let article = db::articles::create(
         &conn,
         auth.id,
         &title,
         &description,
         &body,
-        &new_article.tag_list,
+        &tag_list,
+        status,
     );
     let tmpjson = json!({ "article": article });
     Ok(warp::reply::with_status(
@@ -78,22 +87,27 @@ pub fn route_get_articles(
         .and(warp::path!("api" / "articles"))
         // .and(warp::path::end())
         .and(warp::query::<FindArticles>())
-        .and(routes::extract_auth_head())
+        .and(routes::extract_optional_auth(Scope::Read))
         .and_then(handler_get_articles)
 }
 pub async fn handler_get_articles(
     params: FindArticles,
-    auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+    auth: Option<Auth>,
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_get_articles: {:?}", params);
-    if auth.exp == 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    };
+    let paging = params
+        .paging()
+        .ok_or_else(|| ApiError::validation("cursor", "is invalid"))?;
     let conn = db::PG_POOL.clone().get().unwrap();
-    let user_id = Some(auth.id);
-    let articles = db::articles::find(&conn, &params, user_id);
-    let tmpjson = json!({ "articles": articles.0, "articlesCount": articles.1 });
+    let user_id = auth.map(|auth| auth.id);
+    let page = db::articles::find(&conn, &params, &paging, user_id);
+    // no count with a cursor, that is what makes it fast
+    let tmpjson = json!({
+        "articles": page.items,
+        "articlesCount": page.count,
+        "nextCursor": page.next_cursor,
+        "prevCursor": page.prev_cursor,
+    });
     Ok(warp::reply::with_status(
         warp::reply::json(&tmpjson),
         StatusCode::OK,
@@ -107,33 +121,39 @@ pub fn route_get_article(
         .and(warp::path!("api" / "articles" / String))
         // .and(warp::path("api")).and(warp::path("articles"))
         // .and(warp::path::param())
-        .and(routes::extract_auth_head())
+        .and(routes::extract_optional_auth(Scope::Read))
+        .and(routes::extract_device())
         .and_then(handler_get_article)
 }
-pub async fn handler_get_article(slug: String, auth: Auth) -> Result<impl warp::Reply, Infallible> {
+pub async fn handler_get_article(
+    slug: String,
+    auth: Option<Auth>,
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_get_article: {:?}", slug);
-    // id == 0 means not login, but public_board, allow reading before login
-    // exp == 0 means not login
-    if auth.exp == 0 && auth.id != 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    };
     let conn = db::PG_POOL.clone().get().unwrap();
-    let user_id = Some(auth.id);
-    let re = db::articles::find_one(&conn, &slug, user_id);
-    match re {
-        Some(article) => {
-            let tmpjson = json!({ "article": article });
-            Ok(warp::reply::with_status(
-                warp::reply::json(&tmpjson),
-                StatusCode::OK,
-            ))
-        }
-        None => {
-            let err = Errors::new(&[("Article ", "Not found!")]);
-            return err.respond_to();
+    let user_id = auth.map(|auth| auth.id);
+    if let Some(article) = db::articles::find_one(&conn, &slug, user_id) {
+        // authors reading their own article are not readers
+        let is_author = |id| article.authors.iter().any(|author| author.id == id);
+        if article.status == Status::Published.as_str() && !user_id.is_some_and(is_author) {
+            db::views::record(article.id, db::views::reader(user_id, &device));
         }
+        let tmpjson = json!({ "article": article });
+        return Ok(warp::reply::json(&tmpjson).into_response());
     }
+    // an old slug moved permanently, the article comes along for clients that don't follow
+    let article = db::articles::renamed(&conn, &slug)
+        .and_then(|current| db::articles::find_one(&conn, &current, user_id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let location = format!("/api/articles/{}", article.slug);
+    let tmpjson = json!({ "article": article });
+    Ok(warp::reply::with_header(
+        warp::reply::with_status(warp::reply::json(&tmpjson), StatusCode::MOVED_PERMANENTLY),
+        "Location",
+        location,
+    )
+    .into_response())
 }
 
 // --------------------------------------------------------------------------------------------------------
@@ -141,21 +161,30 @@ pub fn route_get_comments(
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::get()
         .and(warp::path!("api" / "articles" / String / "comments"))
-        .and(routes::extract_auth_head())
+        .and(warp::query::<FindComments>())
+        .and(routes::extract_optional_auth(Scope::Read))
         .and_then(handler_get_comments)
 }
 pub async fn handler_get_comments(
     slug: String,
-    auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
-    log::debug!("handler_get_comments: {:?}", slug);
-    if auth.exp == 0 && auth.id != 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    };
+    params: FindComments,
+    auth: Option<Auth>,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_get_comments: {:?} {:?}", slug, params);
+    let paging = params
+        .paging()
+        .ok_or_else(|| ApiError::validation("cursor", "is invalid"))?;
     let conn = db::PG_POOL.clone().get().unwrap();
-    let comments = db::comments::find_by_slug(&conn, &slug);
-    let tmpjson = json!({ "comments": comments });
+    let user_id = auth.map(|auth| auth.id);
+    db::articles::find_visible(&conn, &slug, user_id)
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let page = db::comments::find_by_slug(&conn, &slug, &params, &paging, user_id);
+    let tmpjson = json!({
+        "comments": page.items,
+        "commentsCount": page.count,
+        "nextCursor": page.next_cursor,
+        "prevCursor": page.prev_cursor,
+    });
     Ok(warp::reply::with_status(
         warp::reply::json(&tmpjson),
         StatusCode::OK,
@@ -173,34 +202,27 @@ pub fn route_update_article(
     warp::put()
         .and(warp::path!("api" / "articles" / String))
         .and(warp::body::json())
-        .and(routes::extract_auth_head())
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
         .and_then(handler_update_article)
 }
 pub async fn handler_update_article(
     slug: String,
     article: UpdateArticle,
     auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_update_article: {:?}", article);
-    if auth.exp == 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    };
+    let mut article = article.article;
     let conn = db::PG_POOL.clone().get().unwrap();
-    let re = db::articles::update(&conn, &slug, auth.id, article.article);
-    match re {
-        Some(article) => {
-            let tmpjson = json!({ "article": article });
-            Ok(warp::reply::with_status(
-                warp::reply::json(&tmpjson),
-                StatusCode::OK,
-            ))
-        }
-        None => {
-            let err = Errors::new(&[("Article ", "Update Error!")]);
-            return err.respond_to();
-        }
-    }
+    article
+        .check_tags(&conn)
+        .map_err(|code| ApiError::validation("tagList", code))?;
+    let article = db::articles::update(&conn, &slug, auth.id, article)
+        .map_err(|err| routes::write_rejection("article", err))?;
+    let tmpjson = json!({ "article": article });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
 }
 
 // --------------------------------------------------------------------------------------------------------
@@ -220,28 +242,24 @@ pub fn route_new_comment(
     warp::post()
         .and(warp::path!("api" / "articles" / String / "comments"))
         .and(warp::body::json())
-        .and(routes::extract_auth_head())
+        .and(routes::extract_scoped_auth(Scope::CommentsWrite))
         .and_then(handler_new_comment)
 }
 pub async fn handler_new_comment(
     slug: String,
     new_comment: NewComment,
     auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_new_comment: {:?}", new_comment);
-    if auth.exp == 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    };
     let new_comment = new_comment.comment;
     let mut extractor = FieldValidator::validate(&new_comment);
     let body = extractor.extract("body", new_comment.body);
-    match extractor.check() {
-        Ok(_) => (),
-        Err(err) => return err.respond_to(),
-    };
+    extractor.check()?;
 
     let conn = db::PG_POOL.clone().get().unwrap();
+    routes::check_verified(&conn, &auth)?;
+    db::articles::find_visible(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
     let comment = db::comments::create(&conn, auth.id, &slug, &body);
     let tmpjson = json!({ "comment": comment });
     Ok(warp::reply::with_status(
@@ -254,45 +272,100 @@ pub fn route_delete_comment(
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::delete()
         .and(warp::path!("api" / "articles" / String / "comments" / i32))
-        .and(routes::extract_auth_head())
+        .and(routes::extract_scoped_auth(Scope::CommentsWrite))
+        .and(routes::extract_device())
         .and_then(handler_delete_comment)
 }
 pub async fn handler_delete_comment(
     slug: String,
     id: i32,
     auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_delete_comment: {:?}", slug);
-
-    if auth.exp == 0 {
-        // let err=Errors::new(&[("Login ", "Login Expired!")]);
-        return Ok(StatusCode::FORBIDDEN);
-    };
     let conn = db::PG_POOL.clone().get().unwrap();
-    db::comments::delete(&conn, auth.id, &slug, id);
+    let comment = db::comments::delete(&conn, &auth, &slug, id)
+        .map_err(|err| routes::write_rejection("comment", err))?;
+    let deleted = json!({ "article": slug, "author": comment.author, "body": comment.body });
+    db::audit::record(
+        &conn,
+        &Actor::from_auth(&auth, device.ip),
+        audit::COMMENT_DELETE,
+        Some(&format!("comment:{}", id)),
+        audit::diff(&deleted, &json!({})),
+    );
     Ok(StatusCode::OK)
 }
 
+// --------------------------------------------------------------------------------------------------------
+#[derive(Deserialize, Debug)]
+pub struct PublishArticle {
+    article: PublishArticleData,
+}
+
+#[derive(Deserialize, Debug)]
+struct PublishArticleData {
+    /// schedule it instead of publishing now
+    #[serde(rename = "publishAt")]
+    publish_at: Option<DateTime<Utc>>,
+}
+
+pub fn route_publish_article(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("api" / "articles" / String / "publish"))
+        .and(warp::body::json())
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
+        .and_then(handler_publish_article)
+}
+pub async fn handler_publish_article(
+    slug: String,
+    publish: PublishArticle,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_publish_article: {} {:?}", slug, publish);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    routes::check_verified(&conn, &auth)?;
+    let article = db::articles::publish(&conn, &slug, auth.id, publish.article.publish_at)
+        .map_err(|err| routes::write_rejection("article", err))?;
+    let tmpjson = json!({ "article": article });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
+}
+
 // --------------------------------------------------------------------------------------------------------
 pub fn route_delete_article(
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::delete()
         .and(warp::path!("api" / "articles" / String))
-        .and(routes::extract_auth_head())
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
+        .and(routes::extract_device())
         .and_then(handler_delete_article)
 }
 pub async fn handler_delete_article(
     slug: String,
     auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_delete_article: {:?}", slug);
-
-    if auth.exp == 0 {
-        // let err=Errors::new(&[("Login ", "Login Expired!")]);
-        return Ok(StatusCode::FORBIDDEN);
-    };
     let conn = db::PG_POOL.clone().get().unwrap();
-    db::articles::delete(&conn, &slug, auth.id);
+    let article = db::articles::delete(&conn, &slug, &auth)
+        .map_err(|err| routes::write_rejection("article", err))?;
+    let deleted = json!({
+        "title": article.title,
+        "description": article.description,
+        "author": article.author,
+        "tagList": article.tag_list,
+    });
+    db::audit::record(
+        &conn,
+        &Actor::from_auth(&auth, device.ip),
+        audit::ARTICLE_DELETE,
+        Some(&format!("article:{}", slug)),
+        audit::diff(&deleted, &json!({})),
+    );
     Ok(StatusCode::OK)
 }
 
@@ -301,68 +374,51 @@ pub fn route_favorite_article(
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::post()
         .and(warp::path!("api" / "articles" / String / "favorite"))
-        .and(routes::extract_auth_head())
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
         .and_then(handler_favorite_article)
 }
 pub async fn handler_favorite_article(
     slug: String,
     auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_favorite_article: {:?}", slug);
 
-    if auth.exp == 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    };
     let conn = db::PG_POOL.clone().get().unwrap();
-    let re = db::articles::favorite(&conn, &slug, auth.id);
-    match re {
-        Some(article) => {
-            let tmpjson = json!({ "article": article });
-            Ok(warp::reply::with_status(
-                warp::reply::json(&tmpjson),
-                StatusCode::OK,
-            ))
-        }
-        None => {
-            let err = Errors::new(&[("Favorite Article ", "Favorite Article Error!")]);
-            return err.respond_to();
-        }
-    }
+    routes::check_verified(&conn, &auth)?;
+    db::articles::find_visible(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let article = db::articles::favorite(&conn, &slug, auth.id)
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let tmpjson = json!({ "article": article });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
 }
 // --------------------------------------------------------------------------------------------------------
 pub fn route_unfavorite_article(
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::delete()
         .and(warp::path!("api" / "articles" / String / "favorite"))
-        .and(routes::extract_auth_head())
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
         .and_then(handler_unfavorite_article)
 }
 pub async fn handler_unfavorite_article(
     slug: String,
     auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_unfavorite_article: {:?}", slug);
 
-    if auth.exp == 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    };
     let conn = db::PG_POOL.clone().get().unwrap();
-    let re = db::articles::unfavorite(&conn, &slug, auth.id);
-    match re {
-        Some(article) => {
-            let tmpjson = json!({ "article": article });
-            Ok(warp::reply::with_status(
-                warp::reply::json(&tmpjson),
-                StatusCode::OK,
-            ))
-        }
-        None => {
-            let err = Errors::new(&[("UnFavorite Article ", "UnFavorite Article Error!")]);
-            return err.respond_to();
-        }
-    }
+    db::articles::find_visible(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let article = db::articles::unfavorite(&conn, &slug, auth.id)
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let tmpjson = json!({ "article": article });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
 }
 // --------------------------------------------------------------------------------------------------------
 // #[get("/articles/feed?<params..>")]
@@ -371,23 +427,26 @@ pub fn route_get_articles_feed(
     warp::get()
         .and(warp::path!("api" / "articles" / "feed"))
         .and(warp::query::<FeedArticles>())
-        .and(routes::extract_auth_head())
+        .and(routes::extract_scoped_auth(Scope::Read))
         .and_then(handler_get_articles_feed)
 }
 pub async fn handler_get_articles_feed(
     params: FeedArticles,
     auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_get_articles_feed: {:?}", params);
+    let paging = params
+        .paging()
+        .ok_or_else(|| ApiError::validation("cursor", "is invalid"))?;
 
-    if auth.exp == 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    };
     let conn = db::PG_POOL.clone().get().unwrap();
-    let articles = db::articles::feed(&conn, &params, auth.id);
-    let articles_count = articles.len();
-    let tmpjson = json!({ "articles": articles, "articlesCount": articles_count });
+    let page = db::articles::feed(&conn, &params, &paging, auth.id);
+    let tmpjson = json!({
+        "articles": page.items,
+        "articlesCount": page.count,
+        "nextCursor": page.next_cursor,
+        "prevCursor": page.prev_cursor,
+    });
     Ok(warp::reply::with_status(
         warp::reply::json(&tmpjson),
         StatusCode::OK,
@@ -0,0 +1,171 @@
+use crate::auth::Auth;
+use crate::db;
+use crate::errors::ApiError;
+use crate::models::article::Article;
+use crate::policy::Scope;
+use crate::routes;
+use diesel::pg::PgConnection;
+use serde::Deserialize;
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+#[derive(Deserialize, Debug)]
+pub struct InviteAuthor {
+    author: InviteAuthorData,
+}
+
+#[derive(Deserialize, Debug)]
+struct InviteAuthorData {
+    username: String,
+}
+
+/// The article if `auth` wrote it, only its author manages the co-authors.
+fn own_article(conn: &PgConnection, slug: &str, auth: &Auth) -> Result<Article, Rejection> {
+    let article = db::articles::find_visible(conn, slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    if article.author != auth.id {
+        return Err(ApiError::forbidden("article", "is not yours to change"));
+    }
+    Ok(article)
+}
+
+fn to_authors_json(
+    conn: &PgConnection,
+    article: &Article,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, Rejection> {
+    let tmpjson = json!({ "authors": db::authors::list(conn, article.id) });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
+}
+
+/// The co-authors and the users invited, for the author and them.
+pub fn route_list_authors(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "articles" / String / "authors"))
+        .and(routes::extract_scoped_auth(Scope::Read))
+        .and_then(handler_list_authors)
+}
+pub async fn handler_list_authors(slug: String, auth: Auth) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_list_authors: {:?}", slug);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let article = db::articles::find_invited(&conn, &slug, auth.id)
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    to_authors_json(&conn, &article)
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_invite_author(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("api" / "articles" / String / "authors"))
+        .and(warp::body::json())
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
+        .and_then(handler_invite_author)
+}
+pub async fn handler_invite_author(
+    slug: String,
+    invite: InviteAuthor,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_invite_author: {:?} {:?}", slug, invite);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    routes::check_verified(&conn, &auth)?;
+    let article = own_article(&conn, &slug, &auth)?;
+    let user = db::users::find_by_username(&conn, &invite.author.username)
+        .ok_or_else(|| ApiError::not_found("profile"))?;
+    if user.id == article.author {
+        return Err(ApiError::validation("username", "is the author"));
+    }
+    db::authors::invite(&conn, article.id, user.id).map_err(|err| {
+        log::error!("handler_invite_author: {}", err);
+        ApiError::internal()
+    })?;
+    to_authors_json(&conn, &article)
+}
+
+// --------------------------------------------------------------------------------------------------------
+/// The author takes anyone off, a co-author or invited user only themselves.
+pub fn route_remove_author(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::delete()
+        .and(warp::path!(
+            "api" / "articles" / String / "authors" / String
+        ))
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
+        .and_then(handler_remove_author)
+}
+pub async fn handler_remove_author(
+    slug: String,
+    username: String,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_remove_author: {:?} {:?}", slug, username);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let article = db::articles::find_invited(&conn, &slug, auth.id)
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let user = db::users::find_by_username(&conn, &username)
+        .ok_or_else(|| ApiError::not_found("profile"))?;
+    if auth.id != article.author && auth.id != user.id {
+        return Err(ApiError::forbidden("article", "is not yours to change"));
+    }
+    let removed = db::authors::remove(&conn, article.id, user.id).map_err(|err| {
+        log::error!("handler_remove_author: {}", err);
+        ApiError::internal()
+    })?;
+    if !removed {
+        return Err(ApiError::not_found("author"));
+    }
+    Ok(StatusCode::OK)
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_accept_invitation(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("api" / "articles" / String / "invitation"))
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
+        .and_then(handler_accept_invitation)
+}
+pub async fn handler_accept_invitation(
+    slug: String,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_accept_invitation: {:?}", slug);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    routes::check_verified(&conn, &auth)?;
+    let article = db::articles::find_invited(&conn, &slug, auth.id)
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let accepted = db::authors::accept(&conn, article.id, auth.id).map_err(|err| {
+        log::error!("handler_accept_invitation: {}", err);
+        ApiError::internal()
+    })?;
+    if !accepted {
+        return Err(ApiError::not_found("invitation"));
+    }
+    let article = db::articles::find_one(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let tmpjson = json!({ "article": article });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_list_invitations(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "user" / "invitations"))
+        .and(routes::extract_scoped_auth(Scope::Read))
+        .and_then(handler_list_invitations)
+}
+pub async fn handler_list_invitations(auth: Auth) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_list_invitations: {:?}", auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let tmpjson = json!({ "invitations": db::authors::invitations(&conn, auth.id) });
+    Ok(warp::reply::json(&tmpjson))
+}
@@ -0,0 +1,138 @@
+use crate::auth::Auth;
+use crate::config;
+use crate::db;
+use crate::db::bookmarks::{BookmarkChanges, FindBookmarks};
+use crate::errors::ApiError;
+use crate::policy::Scope;
+use crate::routes;
+use serde::Deserialize;
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+#[derive(Deserialize, Default, Debug)]
+pub struct NewBookmark {
+    #[serde(default)]
+    bookmark: NewBookmarkData,
+}
+
+#[derive(Deserialize, Default, Debug)]
+struct NewBookmarkData {
+    folder: Option<String>,
+    note: Option<String>,
+}
+
+/// The body is optional, a bookmark made again keeps the folder and note not given.
+pub fn route_bookmark_article(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("api" / "articles" / String / "bookmark"))
+        .and(routes::optional_json())
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
+        .and_then(handler_bookmark_article)
+}
+pub async fn handler_bookmark_article(
+    slug: String,
+    bookmark: NewBookmark,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_bookmark_article: {:?} {:?}", slug, bookmark);
+    let changes = BookmarkChanges::new(bookmark.bookmark.folder, bookmark.bookmark.note);
+    if let Some(Some(ref folder)) = changes.folder {
+        if folder.chars().count() > config::MAX_FOLDER_LENGTH {
+            return Err(ApiError::validation("folder", "is too long"));
+        }
+    }
+    if let Some(Some(ref note)) = changes.note {
+        if note.chars().count() > config::MAX_NOTE_LENGTH {
+            return Err(ApiError::validation("note", "is too long"));
+        }
+    }
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let article = db::articles::find_visible(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let bookmark = db::bookmarks::add(&conn, auth.id, article.id, &changes).map_err(|err| {
+        log::error!("handler_bookmark_article: {}", err);
+        ApiError::internal()
+    })?;
+    let article = db::articles::find_one(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let tmpjson = json!({ "bookmark": bookmark.attach(article) });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
+}
+// --------------------------------------------------------------------------------------------------------
+pub fn route_unbookmark_article(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::delete()
+        .and(warp::path!("api" / "articles" / String / "bookmark"))
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
+        .and_then(handler_unbookmark_article)
+}
+pub async fn handler_unbookmark_article(
+    slug: String,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_unbookmark_article: {:?}", slug);
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let article = db::articles::find_visible(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    db::bookmarks::remove(&conn, auth.id, article.id).map_err(|err| {
+        log::error!("handler_unbookmark_article: {}", err);
+        ApiError::internal()
+    })?;
+    let article = db::articles::find_one(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let tmpjson = json!({ "article": article });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_list_bookmarks(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "user" / "bookmarks"))
+        .and(warp::query::<FindBookmarks>())
+        .and(routes::extract_scoped_auth(Scope::Read))
+        .and_then(handler_list_bookmarks)
+}
+pub async fn handler_list_bookmarks(
+    params: FindBookmarks,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_list_bookmarks: {:?} {:?}", params, auth);
+    let paging = params
+        .paging()
+        .ok_or_else(|| ApiError::validation("cursor", "is invalid"))?;
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let page = db::bookmarks::list(&conn, auth.id, &params, &paging);
+    let tmpjson = json!({
+        "bookmarks": page.items,
+        "bookmarksCount": page.count,
+        "nextCursor": page.next_cursor,
+        "prevCursor": page.prev_cursor,
+    });
+    Ok(warp::reply::json(&tmpjson))
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_list_folders(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "user" / "bookmarks" / "folders"))
+        .and(routes::extract_scoped_auth(Scope::Read))
+        .and_then(handler_list_folders)
+}
+pub async fn handler_list_folders(auth: Auth) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_list_folders: {:?}", auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let tmpjson = json!({ "folders": db::bookmarks::folders(&conn, auth.id) });
+    Ok(warp::reply::json(&tmpjson))
+}
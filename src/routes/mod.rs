@@ -1,24 +1,41 @@
 use crate::auth::{decode_token, Auth};
 use crate::config;
 use crate::db;
+use crate::errors::{self, ApiError, FieldName};
+use crate::keys;
+use crate::models::user::UserAuth;
+use crate::policy::{Permission, Scope};
 use bytes::BufMut;
 use chrono::Utc;
+use diesel::PgConnection;
 use futures::TryStreamExt;
+use serde::de::DeserializeOwned;
 use serde_json::json;
 use std::net::SocketAddr;
 use uuid::Uuid;
 use warp::{
     //    http::StatusCode,
+    http::{header::SET_COOKIE, HeaderValue, Method},
     multipart::{FormData, Part},
-    reject,
     Filter,
     Rejection,
     Reply,
 };
 
+mod access_tokens;
+mod admin;
+mod analytics;
 mod articles;
+mod authors;
+mod bookmarks;
+mod oidc;
 mod profiles;
+mod reactions;
+mod revisions;
+mod search;
+mod sessions;
 mod tags;
+mod two_factor;
 mod users;
 
 pub async fn web_routes() {
@@ -33,6 +50,7 @@ pub async fn web_routes() {
             "Access-Control-Request-Headers",
             "Content-Type",
             "Authorization",
+            "X-CSRF-Token",
             "Accept",
             "Upgrade-Insecure-Requests",
             "Host",
@@ -58,6 +76,9 @@ pub async fn web_routes() {
     let board_mode = warp::get()
         .and(warp::path!("js" / "board_mode.js"))
         .and_then(handle_board_mode);
+    let jwks = warp::get()
+        .and(warp::path!(".well-known" / "jwks.json"))
+        .and_then(handle_jwks);
     let upload = warp::post()
         .and(warp::path("uploadFile"))
         .and(warp::path::end())
@@ -78,25 +99,81 @@ pub async fn web_routes() {
         .map(|tail: warp::path::Tail| {
             format!("testpath: {:?}", tail)
         });*/
-    let apis = users::route_create_user()
+    // grouped, a single long .or() chain overflows the compiler's recursion limit
+    let user_apis = users::route_create_user()
         .or(users::route_user_login())
+        .or(users::route_user_login_2fa())
+        .or(users::route_user_logout())
+        .or(users::route_refresh_token())
+        .or(users::route_verify_email())
+        .or(users::route_resend_verification())
+        .or(users::route_forgot_password())
+        .or(users::route_reset_password())
         .or(users::route_put_user())
         .or(users::route_get_user())
-        .or(articles::route_new_article())
+        .or(oidc::route_oidc_login())
+        .or(oidc::route_oidc_callback());
+    let article_apis = articles::route_new_article()
         .or(articles::route_get_articles_feed())
         .or(articles::route_get_article())
         .or(articles::route_get_articles())
         .or(articles::route_get_comments())
         .or(articles::route_update_article())
+        .or(articles::route_publish_article())
         .or(articles::route_new_comment())
         .or(articles::route_delete_comment())
         .or(articles::route_delete_article())
         .or(articles::route_favorite_article())
         .or(articles::route_unfavorite_article())
-        .or(tags::route_get_tags())
+        .or(search::route_search());
+    let revision_apis = revisions::route_list_revisions()
+        .or(revisions::route_get_revision())
+        .or(revisions::route_diff_revisions())
+        .or(revisions::route_restore_revision());
+    let author_apis = authors::route_list_authors()
+        .or(authors::route_invite_author())
+        .or(authors::route_remove_author())
+        .or(authors::route_accept_invitation())
+        .or(authors::route_list_invitations());
+    let reaction_apis = reactions::route_react_article()
+        .or(reactions::route_unreact_article())
+        .or(reactions::route_react_comment())
+        .or(reactions::route_unreact_comment());
+    let bookmark_apis = bookmarks::route_bookmark_article()
+        .or(bookmarks::route_unbookmark_article())
+        .or(bookmarks::route_list_bookmarks())
+        .or(bookmarks::route_list_folders());
+    let profile_apis = tags::route_get_tags()
         .or(profiles::route_get_profile())
         .or(profiles::route_follow())
-        .or(profiles::route_unfollow());
+        .or(profiles::route_unfollow())
+        .or(analytics::route_get_analytics());
+    let account_apis = two_factor::route_enroll()
+        .or(two_factor::route_confirm())
+        .or(two_factor::route_disable())
+        .or(two_factor::route_recovery_codes())
+        .or(access_tokens::route_list_tokens())
+        .or(access_tokens::route_create_token())
+        .or(access_tokens::route_revoke_token())
+        .or(sessions::route_list_sessions())
+        .or(sessions::route_revoke_session())
+        .or(sessions::route_revoke_other_sessions())
+        .or(admin::route_list_users())
+        .or(admin::route_update_user())
+        .or(admin::route_delete_user())
+        .or(admin::route_audit_events())
+        .or(admin::route_list_tags())
+        .or(admin::route_rename_tag())
+        .or(admin::route_merge_tag())
+        .or(admin::route_block_tag());
+    let apis = user_apis
+        .or(article_apis)
+        .or(revision_apis)
+        .or(author_apis)
+        .or(reaction_apis)
+        .or(bookmark_apis)
+        .or(profile_apis)
+        .or(account_apis);
     let routes = apis
         .or(index)
         .or(index1)
@@ -106,52 +183,286 @@ pub async fn web_routes() {
         .or(downloads)
         .or(upload)
         .or(board_mode)
+        .or(jwks)
+        .recover(errors::recover)
         .with(&cors);
 
     let addr: SocketAddr = db::CONFIG.web_url.parse().unwrap();
     warp::serve(routes).run(addr).await;
 }
 
-// "Rejection: HTTP method not allowed" when Authorization head not found or decode error
-pub fn extract_auth_head() -> impl Filter<Extract = (Auth,), Error = warp::Rejection> + Copy {
-    // warp::header::<String>("authorization").and_then(|s: String| async move {
-    warp::header::optional::<String>("authorization").and_then(|ops: Option<String>| async move {
-        let s = match ops {
-            Some(s) => s,
-            None => String::from(""),
-        };
-        if s.starts_with(config::TOKEN_PREFIX) {
-            let auth = decode_token(&s[config::TOKEN_PREFIX.len()..]);
-            match auth {
-                Some(mut auth) => {
-                    if auth.exp < Utc::now().timestamp() {
-                        auth.exp = 0;
-                    }
-                    log::debug!("auth head : {:?}", auth);
-                    Ok(auth)
+/// The token of a request, from the `Authorization` header or with
+/// `COOKIE_SESSIONS` from the session cookie. A write with the cookie has to
+/// repeat the CSRF token of its session in the `X-CSRF-Token` header.
+pub fn request_token() -> impl Filter<Extract = (Option<String>,), Error = warp::Rejection> + Copy {
+    warp::header::optional::<String>("authorization")
+        .and(warp::cookie::optional::<String>(config::SESSION_COOKIE))
+        .and(warp::header::optional::<String>(config::CSRF_HEADER))
+        .and(warp::method())
+        .and_then(
+            |header: Option<String>,
+             session: Option<String>,
+             csrf_header: Option<String>,
+             method: Method| async move {
+                if let Some(header) = header {
+                    return Ok(header
+                        .strip_prefix(config::TOKEN_PREFIX)
+                        .map(str::to_string));
+                }
+                let session = match session {
+                    Some(session) if db::CONFIG.cookie_sessions => session,
+                    _ => return Ok(None),
+                };
+                if method.is_safe() {
+                    return Ok(Some(session));
                 }
-                None => {
-                    if db::CONFIG.public_board {
-                        Ok(Auth {
-                            exp: 0,
-                            id: 0,
-                            username: String::from(""),
-                        })
-                    } else {
-                        Err(reject::not_found())
+                let sid = match decode_token(&session) {
+                    Some(auth) => auth.sid,
+                    // no login anyway, authenticate answers 401
+                    None => return Ok(Some(session)),
+                };
+                let conn = db::PG_POOL.clone().get().unwrap();
+                match (db::sessions::csrf_token(&conn, sid), csrf_header) {
+                    (Some(token), Some(header)) if db::secrets_equal(&token, &header) => {
+                        Ok(Some(session))
                     }
+                    _ => Err(ApiError::forbidden("csrf token", "is missing or wrong")),
                 }
-            }
+            },
+        )
+}
+
+/// The login of a request, None without a token on a public board. Personal
+/// access tokens only count on routes that take them with a `scope`.
+async fn authenticate(
+    token: Option<String>,
+    scope: Option<Scope>,
+) -> Result<Option<Auth>, Rejection> {
+    let token = match token {
+        Some(token) => token,
+        None if db::CONFIG.public_board => return Ok(None),
+        None => return Err(ApiError::unauthorized()),
+    };
+    let conn = db::PG_POOL.clone().get().unwrap();
+    if token.starts_with(db::access_tokens::TOKEN_PREFIX) {
+        let auth =
+            db::access_tokens::authenticate(&conn, &token).ok_or_else(ApiError::unauthorized)?;
+        return match scope {
+            Some(scope) if auth.has_scope(scope) => Ok(Some(auth)),
+            Some(_) => Err(ApiError::forbidden("token", "lacks the scope")),
+            None => Err(ApiError::forbidden("token", "can't be used here")),
+        };
+    }
+    match decode_token(&token) {
+        // a session is gone after logout, or when the password was changed since
+        Some(auth)
+            if auth.exp >= Utc::now().timestamp() && db::sessions::touch(&conn, auth.sid) =>
+        {
+            log::debug!("auth head : {:?}", auth);
+            Ok(Some(auth))
+        }
+        _ => Err(ApiError::unauthorized()),
+    }
+}
+
+fn request_auth(
+    scope: Option<Scope>,
+) -> impl Filter<Extract = (Option<Auth>,), Error = warp::Rejection> + Copy {
+    request_token().and_then(move |token: Option<String>| authenticate(token, scope))
+}
+
+async fn require_login(auth: Option<Auth>) -> Result<Auth, Rejection> {
+    auth.ok_or_else(ApiError::unauthorized)
+}
+
+/// The logged-in user, 401 without a valid login.
+pub fn extract_auth_head() -> impl Filter<Extract = (Auth,), Error = warp::Rejection> + Copy {
+    request_auth(None).and_then(require_login)
+}
+
+/// `extract_auth_head` for routes that also take personal access tokens with `scope`.
+pub fn extract_scoped_auth(
+    scope: Scope,
+) -> impl Filter<Extract = (Auth,), Error = warp::Rejection> + Copy {
+    request_auth(Some(scope)).and_then(require_login)
+}
+
+/// For routes anyone can read on a public board; a bad token is still a 401.
+pub fn extract_optional_auth(
+    scope: Scope,
+) -> impl Filter<Extract = (Option<Auth>,), Error = warp::Rejection> + Copy {
+    request_auth(Some(scope))
+}
+
+/// The user agent and address of the client, for the sessions it opens.
+pub fn extract_device(
+) -> impl Filter<Extract = (db::sessions::Device,), Error = warp::Rejection> + Copy {
+    warp::header::optional::<String>("user-agent")
+        .and(warp::addr::remote())
+        .map(
+            |user_agent: Option<String>, remote: Option<SocketAddr>| db::sessions::Device {
+                user_agent,
+                ip: remote.map(|addr| addr.ip()),
+            },
+        )
+}
+
+/// A `Set-Cookie` value, `max_age` 0 removes the cookie.
+fn cookie(name: &str, value: &str, path: &str, max_age: i64, http_only: bool) -> HeaderValue {
+    same_site_cookie(name, value, path, max_age, http_only, "Strict")
+}
+
+/// `cookie` with another `SameSite`, `Lax` for one that comes back from another site.
+pub fn same_site_cookie(
+    name: &str,
+    value: &str,
+    path: &str,
+    max_age: i64,
+    http_only: bool,
+    same_site: &str,
+) -> HeaderValue {
+    let mut cookie = format!(
+        "{}={}; Path={}; Max-Age={}; SameSite={}",
+        name, value, path, max_age, same_site
+    );
+    if http_only {
+        cookie.push_str("; HttpOnly");
+    }
+    if db::CONFIG.cookie_secure {
+        cookie.push_str("; Secure");
+    }
+    HeaderValue::from_str(&cookie).expect("Invalid cookie")
+}
+
+const REFRESH_COOKIE_PATH: &str = "/api/users/token/refresh";
+
+/// The reply to a login or a token refresh. With `COOKIE_SESSIONS` the tokens
+/// also go into HttpOnly cookies, so the front-end needn't store them.
+pub fn user_auth_reply(
+    conn: &PgConnection,
+    user_auth: UserAuth,
+    sid: i32,
+) -> warp::reply::Response {
+    let response = warp::reply::json(&json!({ "user": user_auth })).into_response();
+    with_session_cookies(conn, response, &user_auth, sid)
+}
+
+/// Add the cookies of `COOKIE_SESSIONS` for a login to a reply.
+pub fn with_session_cookies(
+    conn: &PgConnection,
+    mut response: warp::reply::Response,
+    user_auth: &UserAuth,
+    sid: i32,
+) -> warp::reply::Response {
+    if !db::CONFIG.cookie_sessions {
+        return response;
+    }
+    let login_seconds = config::LOGIN_EXPIRE_DAYS * 24 * 60 * 60;
+    // gone with the access token, the next refresh sets them again
+    let access_seconds = config::ACCESS_EXPIRE_MINUTES * 60;
+    let headers = response.headers_mut();
+    headers.append(
+        SET_COOKIE,
+        cookie(
+            config::SESSION_COOKIE,
+            user_auth.token(),
+            "/",
+            access_seconds,
+            true,
+        ),
+    );
+    if let Some(csrf_token) = db::sessions::csrf_token(conn, sid) {
+        headers.append(
+            SET_COOKIE,
+            cookie(config::CSRF_COOKIE, &csrf_token, "/", access_seconds, false),
+        );
+    }
+    if let Some(refresh_token) = user_auth.refresh_token() {
+        headers.append(
+            SET_COOKIE,
+            cookie(
+                config::REFRESH_COOKIE,
+                refresh_token,
+                REFRESH_COOKIE_PATH,
+                login_seconds,
+                true,
+            ),
+        );
+    }
+    response
+}
+
+/// Remove the cookies of `COOKIE_SESSIONS` from the browser.
+pub fn clear_session_cookies(reply: impl Reply) -> warp::reply::Response {
+    let mut response = reply.into_response();
+    if db::CONFIG.cookie_sessions {
+        let headers = response.headers_mut();
+        headers.append(SET_COOKIE, cookie(config::SESSION_COOKIE, "", "/", 0, true));
+        headers.append(SET_COOKIE, cookie(config::CSRF_COOKIE, "", "/", 0, false));
+        headers.append(
+            SET_COOKIE,
+            cookie(config::REFRESH_COOKIE, "", REFRESH_COOKIE_PATH, 0, true),
+        );
+    }
+    response
+}
+
+/// With `REQUIRE_VERIFIED` nothing can be written before the email is verified.
+pub fn check_verified(conn: &PgConnection, auth: &Auth) -> Result<(), Rejection> {
+    if !db::CONFIG.require_verified || db::users::is_verified(conn, auth.id) {
+        Ok(())
+    } else {
+        Err(ApiError::forbidden("email", "is not verified"))
+    }
+}
+
+/// 404 or 403 for a write to `field` the db layer refused.
+pub fn write_rejection(field: FieldName, err: db::WriteError) -> Rejection {
+    match err {
+        db::WriteError::NotFound => ApiError::not_found(field),
+        db::WriteError::Forbidden => ApiError::forbidden(field, "is not yours to change"),
+        db::WriteError::Conflict => ApiError::conflict(field, "was changed at the same time"),
+    }
+}
+
+/// A path segment as sent, e.g. a tag with spaces; warp leaves it percent-encoded.
+pub fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
         } else {
-            if db::CONFIG.public_board {
-                Ok(Auth {
-                    exp: 0,
-                    id: 0,
-                    username: String::from(""),
-                })
-            } else {
-                Err(reject::not_found())
-            }
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// A json body that may be left out, `T::default()` then.
+pub fn optional_json<T: DeserializeOwned + Default + Send + 'static>(
+) -> impl Filter<Extract = (T,), Error = warp::Rejection> + Copy {
+    warp::body::bytes().and_then(|body: bytes::Bytes| async move {
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(T::default());
+        }
+        serde_json::from_slice(&body).map_err(|_| ApiError::validation("body", "is invalid"))
+    })
+}
+
+/// `extract_auth_head` for routes that need a permission, e.g. the admin api.
+pub fn require_permission(
+    permission: Permission,
+) -> impl Filter<Extract = (Auth,), Error = warp::Rejection> + Copy {
+    extract_auth_head().and_then(move |auth: Auth| async move {
+        if auth.role.can(permission) {
+            Ok(auth)
+        } else {
+            Err(ApiError::forbidden("user", "is not allowed to"))
         }
     })
 }
@@ -200,3 +511,7 @@ async fn handle_upload(form: FormData) -> Result<impl Reply, Rejection> {
 async fn handle_board_mode() -> Result<impl Reply, Rejection> {
     Ok(format!("window.board_mode={};\n", db::CONFIG.public_board))
 }
+
+async fn handle_jwks() -> Result<impl Reply, Rejection> {
+    Ok(warp::reply::json(&keys::KEYRING.jwks()))
+}
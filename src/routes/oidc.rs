@@ -0,0 +1,158 @@
+use crate::config;
+use crate::db::{self, identities::IdentityError, sessions::Device};
+use crate::errors::ApiError;
+use crate::oidc;
+use crate::routes;
+use serde::Deserialize;
+use warp::http::{header::SET_COOKIE, HeaderValue, Uri};
+use warp::{Filter, Rejection, Reply};
+
+/// 404 unless `OIDC_ISSUER` is configured
+fn oidc_enabled() -> impl Filter<Extract = (), Error = warp::Rejection> + Copy {
+    warp::any()
+        .and_then(|| async {
+            if oidc::is_enabled() {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+}
+
+const STATE_COOKIE_PATH: &str = "/api/users/oidc";
+
+/// The state of a login in progress, `Lax` so it comes back with the redirect
+/// of the provider. `max_age` 0 removes it.
+fn state_cookie(state: &str, max_age: i64) -> HeaderValue {
+    routes::same_site_cookie(
+        config::OIDC_STATE_COOKIE,
+        state,
+        STATE_COOKIE_PATH,
+        max_age,
+        true,
+        "Lax",
+    )
+}
+
+/// Send the browser to the front-end at `path` after the callback.
+fn front_end_redirect(path: &str) -> Result<warp::reply::Response, Rejection> {
+    let uri = format!("{}/#/{}", db::CONFIG.public_url, path)
+        .parse::<Uri>()
+        .map_err(|err| {
+            log::error!("oidc callback: PUBLIC_URL: {}", err);
+            ApiError::internal()
+        })?;
+    let mut response = warp::redirect::temporary(uri).into_response();
+    response
+        .headers_mut()
+        .append(SET_COOKIE, state_cookie("", 0));
+    Ok(response)
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_oidc_login() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::get()
+        .and(warp::path!("api" / "users" / "oidc" / "login"))
+        .and(oidc_enabled())
+        .and_then(handler_oidc_login)
+}
+pub async fn handler_oidc_login() -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_oidc_login");
+    let uri = oidc::authorization_url().await.and_then(|(url, state)| {
+        let uri = url.parse::<Uri>().map_err(|err| err.to_string())?;
+        Ok((uri, state))
+    });
+    match uri {
+        Ok((uri, state)) => {
+            let mut response = warp::redirect::temporary(uri).into_response();
+            response.headers_mut().append(
+                SET_COOKIE,
+                state_cookie(&state, config::OIDC_LOGIN_EXPIRE_MINUTES * 60),
+            );
+            Ok(response)
+        }
+        Err(err) => {
+            log::error!("oidc login: {}", err);
+            Err(ApiError::internal())
+        }
+    }
+}
+
+// --------------------------------------------------------------------------------------------------------
+#[derive(Deserialize, Debug)]
+pub struct Callback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+pub fn route_oidc_callback(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "users" / "oidc" / "callback"))
+        .and(oidc_enabled())
+        .and(warp::query::<Callback>())
+        .and(warp::cookie::optional::<String>(config::OIDC_STATE_COOKIE))
+        .and(routes::extract_device())
+        .and_then(handler_oidc_callback)
+}
+/// Logs in with the session cookies and sends the browser back to the
+/// front-end, to `#/login?challenge=` when the second factor has to follow.
+pub async fn handler_oidc_callback(
+    callback: Callback,
+    state_cookie: Option<String>,
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_oidc_callback: {:?}", callback);
+    let (code, state) = match callback {
+        Callback {
+            code: Some(code),
+            state: Some(state),
+            error: None,
+        } => (code, state),
+        _ => {
+            return Err(ApiError::forbidden(
+                "login",
+                "was refused by the identity provider",
+            ))
+        }
+    };
+    // a callback the browser didn't start is someone else's login
+    if !state_cookie.is_some_and(|cookie| db::secrets_equal(&cookie, &state)) {
+        return Err(ApiError::forbidden(
+            "login",
+            "was not started in this browser",
+        ));
+    }
+
+    let identity = match oidc::exchange(&code, &state).await {
+        Ok(identity) => identity,
+        Err(err) => {
+            log::warn!("oidc callback: {}", err);
+            return Err(ApiError::bad_credentials("login"));
+        }
+    };
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    match db::identities::find_or_provision(&conn, &identity) {
+        Ok(user) => match super::users::two_factor_challenge(&conn, &user) {
+            Some(challenge) => front_end_redirect(&format!("login?challenge={}", challenge)),
+            None => {
+                let (user_auth, sid) = super::users::start_session(&conn, &user, &device);
+                let response = front_end_redirect("")?;
+                Ok(routes::with_session_cookies(
+                    &conn, response, &user_auth, sid,
+                ))
+            }
+        },
+        Err(IdentityError::NoEmail) => Err(ApiError::forbidden(
+            "email",
+            "is not shared by the provider",
+        )),
+        Err(IdentityError::EmailTaken) => {
+            Err(ApiError::conflict("email", "has already been taken"))
+        }
+    }
+}
@@ -1,16 +1,16 @@
 use crate::auth::Auth;
 use crate::db;
-use crate::errors::Errors;
+use crate::errors::ApiError;
 use crate::models::user::Profile;
+use crate::policy::Scope;
 use crate::routes;
 use serde_json::json;
-use std::convert::Infallible;
 use warp::http::StatusCode;
-use warp::Filter;
+use warp::{Filter, Rejection};
 
 fn to_profile_json(
     profile: Profile,
-) -> Result<warp::reply::WithStatus<warp::reply::Json>, Infallible> {
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, Rejection> {
     let tmpjson = json!({ "profile": profile });
     Ok(warp::reply::with_status(
         warp::reply::json(&tmpjson),
@@ -23,28 +23,19 @@ pub fn route_get_profile(
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::get()
         .and(warp::path!("api" / "profiles" / String))
-        .and(routes::extract_auth_head())
+        .and(routes::extract_optional_auth(Scope::Read))
         .and_then(handler_get_profile)
 }
 pub async fn handler_get_profile(
     username: String,
-    auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+    auth: Option<Auth>,
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_get_profile: {:?}", username);
-    let user_id = Some(auth.id);
-    if auth.exp == 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    };
+    let user_id = auth.map(|auth| auth.id);
     let conn = db::PG_POOL.clone().get().unwrap();
-    let re = db::profiles::find(&conn, &username, user_id);
-    match re {
-        Some(re) => to_profile_json(re),
-        None => {
-            let err = Errors::new(&[("handler_get_profile ", "handler_get_profile Error!")]);
-            return err.respond_to();
-        }
-    }
+    let profile = db::profiles::find(&conn, &username, user_id)
+        .ok_or_else(|| ApiError::not_found("profile"))?;
+    to_profile_json(profile)
 }
 // --------------------------------------------------------------------------------------------------------
 // #[post("/profiles/<username>/follow")]
@@ -54,22 +45,12 @@ pub fn route_follow() -> impl Filter<Extract = impl warp::Reply, Error = warp::R
         .and(routes::extract_auth_head())
         .and_then(handler_follow)
 }
-pub async fn handler_follow(username: String, auth: Auth) -> Result<impl warp::Reply, Infallible> {
+pub async fn handler_follow(username: String, auth: Auth) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_follow: {:?}", username);
-
-    if auth.exp == 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    };
     let conn = db::PG_POOL.clone().get().unwrap();
-    let re = db::profiles::follow(&conn, &username, auth.id);
-    match re {
-        Some(re) => to_profile_json(re),
-        None => {
-            let err = Errors::new(&[("handler_follow ", "handler_follow Error!")]);
-            return err.respond_to();
-        }
-    }
+    let profile = db::profiles::follow(&conn, &username, auth.id)
+        .ok_or_else(|| ApiError::not_found("profile"))?;
+    to_profile_json(profile)
 }
 
 // --------------------------------------------------------------------------------------------------------
@@ -81,23 +62,10 @@ pub fn route_unfollow() -> impl Filter<Extract = impl warp::Reply, Error = warp:
         .and(routes::extract_auth_head())
         .and_then(handler_unfollow)
 }
-pub async fn handler_unfollow(
-    username: String,
-    auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+pub async fn handler_unfollow(username: String, auth: Auth) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_unfollow: {:?}", username);
-
-    if auth.exp == 0 {
-        let err = Errors::new(&[("Login ", "Login Expired!")]);
-        return err.respond_to();
-    };
     let conn = db::PG_POOL.clone().get().unwrap();
-    let re = db::profiles::unfollow(&conn, &username, auth.id);
-    match re {
-        Some(re) => to_profile_json(re),
-        None => {
-            let err = Errors::new(&[("handler_unfollow ", "handler_unfollow Error!")]);
-            return err.respond_to();
-        }
-    }
+    let profile = db::profiles::unfollow(&conn, &username, auth.id)
+        .ok_or_else(|| ApiError::not_found("profile"))?;
+    to_profile_json(profile)
 }
@@ -0,0 +1,159 @@
+use crate::auth::Auth;
+use crate::db;
+use crate::errors::ApiError;
+use crate::policy::Scope;
+use crate::routes;
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+/// The emoji of a path segment, if it is one of `REACTIONS`.
+fn emoji_param(segment: &str) -> Result<String, Rejection> {
+    routes::percent_decode(segment)
+        .filter(|emoji| db::reactions::is_allowed(emoji))
+        .ok_or_else(|| ApiError::validation("reaction", "is not allowed"))
+}
+
+pub fn route_react_article(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!(
+            "api" / "articles" / String / "reactions" / String
+        ))
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
+        .and_then(handler_react_article)
+}
+pub async fn handler_react_article(
+    slug: String,
+    emoji: String,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_react_article: {:?} {:?}", slug, emoji);
+    let emoji = emoji_param(&emoji)?;
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    routes::check_verified(&conn, &auth)?;
+    let article = db::articles::find_visible(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    db::reactions::add_to_article(&conn, article.id, auth.id, &emoji).map_err(|err| {
+        log::error!("handler_react_article: {}", err);
+        ApiError::internal()
+    })?;
+    let article = db::articles::find_one(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let tmpjson = json!({ "article": article });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
+}
+// --------------------------------------------------------------------------------------------------------
+pub fn route_unreact_article(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::delete()
+        .and(warp::path!(
+            "api" / "articles" / String / "reactions" / String
+        ))
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
+        .and_then(handler_unreact_article)
+}
+pub async fn handler_unreact_article(
+    slug: String,
+    emoji: String,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_unreact_article: {:?} {:?}", slug, emoji);
+    // an emoji taken out of `REACTIONS` can still be taken back
+    let emoji = routes::percent_decode(&emoji)
+        .ok_or_else(|| ApiError::validation("reaction", "is invalid"))?;
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let article = db::articles::find_visible(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    db::reactions::remove_from_article(&conn, article.id, auth.id, &emoji).map_err(|err| {
+        log::error!("handler_unreact_article: {}", err);
+        ApiError::internal()
+    })?;
+    let article = db::articles::find_one(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let tmpjson = json!({ "article": article });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_react_comment(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!(
+            "api" / "articles" / String / "comments" / i32 / "reactions" / String
+        ))
+        .and(routes::extract_scoped_auth(Scope::CommentsWrite))
+        .and_then(handler_react_comment)
+}
+pub async fn handler_react_comment(
+    slug: String,
+    id: i32,
+    emoji: String,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_react_comment: {:?} {} {:?}", slug, id, emoji);
+    let emoji = emoji_param(&emoji)?;
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    routes::check_verified(&conn, &auth)?;
+    db::articles::find_visible(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    db::comments::find_one(&conn, &slug, id, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("comment"))?;
+    db::reactions::add_to_comment(&conn, id, auth.id, &emoji).map_err(|err| {
+        log::error!("handler_react_comment: {}", err);
+        ApiError::internal()
+    })?;
+    let comment = db::comments::find_one(&conn, &slug, id, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("comment"))?;
+    let tmpjson = json!({ "comment": comment });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
+}
+// --------------------------------------------------------------------------------------------------------
+pub fn route_unreact_comment(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::delete()
+        .and(warp::path!(
+            "api" / "articles" / String / "comments" / i32 / "reactions" / String
+        ))
+        .and(routes::extract_scoped_auth(Scope::CommentsWrite))
+        .and_then(handler_unreact_comment)
+}
+pub async fn handler_unreact_comment(
+    slug: String,
+    id: i32,
+    emoji: String,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_unreact_comment: {:?} {} {:?}", slug, id, emoji);
+    let emoji = routes::percent_decode(&emoji)
+        .ok_or_else(|| ApiError::validation("reaction", "is invalid"))?;
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    db::articles::find_visible(&conn, &slug, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    db::comments::find_one(&conn, &slug, id, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("comment"))?;
+    db::reactions::remove_from_comment(&conn, id, auth.id, &emoji).map_err(|err| {
+        log::error!("handler_unreact_comment: {}", err);
+        ApiError::internal()
+    })?;
+    let comment = db::comments::find_one(&conn, &slug, id, Some(auth.id))
+        .ok_or_else(|| ApiError::not_found("comment"))?;
+    let tmpjson = json!({ "comment": comment });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
+}
@@ -0,0 +1,108 @@
+use crate::auth::Auth;
+use crate::db;
+use crate::errors::ApiError;
+use crate::policy::Scope;
+use crate::routes;
+use serde::Deserialize;
+use serde_json::json;
+use warp::{Filter, Rejection};
+
+pub fn route_list_revisions(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "articles" / String / "revisions"))
+        .and(routes::extract_optional_auth(Scope::Read))
+        .and_then(handler_list_revisions)
+}
+pub async fn handler_list_revisions(
+    slug: String,
+    auth: Option<Auth>,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_list_revisions: {:?}", slug);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let article = db::articles::find_visible(&conn, &slug, auth.map(|auth| auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let revisions = db::revisions::list(&conn, article.id);
+    let tmpjson = json!({ "revisionsCount": revisions.len(), "revisions": revisions });
+    Ok(warp::reply::json(&tmpjson))
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_get_revision(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "articles" / String / "revisions" / i32))
+        .and(routes::extract_optional_auth(Scope::Read))
+        .and_then(handler_get_revision)
+}
+pub async fn handler_get_revision(
+    slug: String,
+    number: i32,
+    auth: Option<Auth>,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_get_revision: {} {}", slug, number);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let article = db::articles::find_visible(&conn, &slug, auth.map(|auth| auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let (revision, editor) = db::revisions::find(&conn, article.id, number)
+        .ok_or_else(|| ApiError::not_found("revision"))?;
+    let tmpjson = json!({ "revision": revision.attach(editor) });
+    Ok(warp::reply::json(&tmpjson))
+}
+
+// --------------------------------------------------------------------------------------------------------
+#[derive(Deserialize, Debug)]
+pub struct DiffRevisions {
+    /// the revision before by default
+    from: Option<i32>,
+}
+
+pub fn route_diff_revisions(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!(
+            "api" / "articles" / String / "revisions" / i32 / "diff"
+        ))
+        .and(warp::query::<DiffRevisions>())
+        .and(routes::extract_optional_auth(Scope::Read))
+        .and_then(handler_diff_revisions)
+}
+pub async fn handler_diff_revisions(
+    slug: String,
+    number: i32,
+    params: DiffRevisions,
+    auth: Option<Auth>,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_diff_revisions: {} {} {:?}", slug, number, params);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let article = db::articles::find_visible(&conn, &slug, auth.map(|auth| auth.id))
+        .ok_or_else(|| ApiError::not_found("article"))?;
+    let from = params.from.unwrap_or(number - 1);
+    let diff = db::revisions::diff(&conn, &slug, article.id, from, number)
+        .ok_or_else(|| ApiError::not_found("revision"))?;
+    let tmpjson = json!({ "from": from, "to": number, "diff": diff });
+    Ok(warp::reply::json(&tmpjson))
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_restore_revision(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!(
+            "api" / "articles" / String / "revisions" / i32 / "restore"
+        ))
+        .and(routes::extract_scoped_auth(Scope::ArticlesWrite))
+        .and_then(handler_restore_revision)
+}
+pub async fn handler_restore_revision(
+    slug: String,
+    number: i32,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_restore_revision: {} {}", slug, number);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let article = db::articles::restore(&conn, &slug, auth.id, number)
+        .map_err(|err| routes::write_rejection("article", err))?;
+    let tmpjson = json!({ "article": article });
+    Ok(warp::reply::json(&tmpjson))
+}
@@ -0,0 +1,51 @@
+use crate::auth::Auth;
+use crate::db;
+use crate::db::search::{Scope as SearchScope, Search};
+use crate::errors::{ApiError, FieldValidator};
+use crate::policy::Scope;
+use crate::routes;
+use serde_json::{json, Map};
+use warp::{Filter, Rejection};
+
+pub fn route_search() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "search"))
+        .and(warp::query::<Search>())
+        .and(routes::extract_optional_auth(Scope::Read))
+        .and_then(handler_search)
+}
+pub async fn handler_search(
+    params: Search,
+    auth: Option<Auth>,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_search: {:?}", params);
+    let mut extractor = FieldValidator::default();
+    let q = extractor.extract("q", params.q.clone().filter(|q| !q.trim().is_empty()));
+    extractor.check()?;
+    let scopes = params
+        .scopes()
+        .ok_or_else(|| ApiError::validation("scope", "is invalid"))?;
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let user_id = auth.map(|auth| auth.id);
+    let mut results = Map::new();
+    for scope in scopes {
+        let (name, hits, count) = match scope {
+            SearchScope::Articles => {
+                let (hits, count) = db::search::articles(&conn, &q, &params, user_id);
+                ("articles", json!(hits), count)
+            }
+            SearchScope::Comments => {
+                let (hits, count) = db::search::comments(&conn, &q, &params, user_id);
+                ("comments", json!(hits), count)
+            }
+            SearchScope::Users => {
+                let (hits, count) = db::search::users(&conn, &q, &params, user_id);
+                ("users", json!(hits), count)
+            }
+        };
+        results.insert(name.to_string(), hits);
+        results.insert(format!("{}Count", name), json!(count));
+    }
+    Ok(warp::reply::json(&results))
+}
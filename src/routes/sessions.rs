@@ -0,0 +1,73 @@
+use crate::auth::Auth;
+use crate::db;
+use crate::errors::ApiError;
+use crate::routes;
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_list_sessions(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("api" / "user" / "sessions"))
+        .and(routes::extract_auth_head())
+        .and_then(handler_list_sessions)
+}
+pub async fn handler_list_sessions(auth: Auth) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_list_sessions: {:?}", auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let sessions: Vec<_> = db::sessions::list(&conn, auth.id)
+        .into_iter()
+        .map(|s| s.attach(auth.sid))
+        .collect();
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({ "sessions": sessions })),
+        StatusCode::OK,
+    ))
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_revoke_session(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::delete()
+        .and(warp::path!("api" / "user" / "sessions" / i32))
+        .and(routes::extract_auth_head())
+        .and_then(handler_revoke_session)
+}
+pub async fn handler_revoke_session(id: i32, auth: Auth) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_revoke_session: {} {:?}", id, auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    if db::sessions::revoke(&conn, id, auth.id) {
+        Ok(warp::reply::with_status(
+            warp::reply::json(&json!({})),
+            StatusCode::OK,
+        ))
+    } else {
+        Err(ApiError::not_found("session"))
+    }
+}
+
+// --------------------------------------------------------------------------------------------------------
+/// Log out everywhere else.
+pub fn route_revoke_other_sessions(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::delete()
+        .and(warp::path!("api" / "user" / "sessions"))
+        .and(routes::extract_auth_head())
+        .and_then(handler_revoke_other_sessions)
+}
+pub async fn handler_revoke_other_sessions(auth: Auth) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_revoke_other_sessions: {:?}", auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    match db::sessions::revoke_others(&conn, auth.id, auth.sid) {
+        Ok(count) => Ok(warp::reply::with_status(
+            warp::reply::json(&json!({ "revoked": count })),
+            StatusCode::OK,
+        )),
+        Err(err) => {
+            eprintln!("sessions::revoke_others: {}", err);
+            Err(ApiError::internal())
+        }
+    }
+}
@@ -12,6 +12,8 @@ pub fn route_get_tags() -> impl Filter<Extract = impl warp::Reply, Error = warp:
 pub async fn handler_get_tags() -> Result<impl warp::Reply, Infallible> {
     log::debug!("handler_get_tags...");
     let conn = db::PG_POOL.clone().get().unwrap();
-    let tmpjson = json!({ "tags": db::articles::tags(&conn) });
+    let counts = db::tags::popular(&conn);
+    let tags: Vec<_> = counts.iter().map(|count| &count.tag).collect();
+    let tmpjson = json!({ "tags": tags, "tagCounts": counts });
     Ok(warp::reply::json(&tmpjson))
 }
@@ -0,0 +1,125 @@
+use crate::auth::Auth;
+use crate::db;
+use crate::errors::{ApiError, Errors, FieldValidator};
+use crate::routes;
+use crate::totp;
+use serde::Deserialize;
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+#[derive(Deserialize, Debug)]
+pub struct TwoFactorCode {
+    code: Option<String>,
+}
+
+fn extract_code(code: TwoFactorCode) -> Result<String, Errors> {
+    let mut extractor = FieldValidator::default();
+    let code = extractor.extract("code", code.code);
+    extractor.check().map(|_| code)
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_enroll() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("api" / "user" / "2fa" / "enroll"))
+        .and(routes::extract_auth_head())
+        .and_then(handler_enroll)
+}
+pub async fn handler_enroll(auth: Auth) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_enroll: {:?}", auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let user = match db::users::find(&conn, auth.id) {
+        Some(user) => user,
+        None => return Err(ApiError::not_found("user")),
+    };
+    match db::two_factor::start_enrollment(&conn, auth.id) {
+        Some(secret) => {
+            let uri = totp::provisioning_uri(&db::CONFIG.server_name, &user.email, &secret);
+            let tmpjson = json!({ "secret": secret, "uri": uri });
+            Ok(warp::reply::with_status(
+                warp::reply::json(&tmpjson),
+                StatusCode::OK,
+            ))
+        }
+        None => Err(ApiError::conflict("2fa", "is already enabled")),
+    }
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_confirm() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("api" / "user" / "2fa" / "confirm"))
+        .and(warp::body::json())
+        .and(routes::extract_auth_head())
+        .and_then(handler_confirm)
+}
+pub async fn handler_confirm(
+    code: TwoFactorCode,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_confirm: {:?}", auth);
+    let code = extract_code(code)?;
+    let conn = db::PG_POOL.clone().get().unwrap();
+    match db::two_factor::confirm(&conn, auth.id, &code) {
+        Some(codes) => {
+            let tmpjson = json!({ "recoveryCodes": codes });
+            Ok(warp::reply::with_status(
+                warp::reply::json(&tmpjson),
+                StatusCode::OK,
+            ))
+        }
+        None => Err(ApiError::validation("code", "is invalid")),
+    }
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_disable() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("api" / "user" / "2fa" / "disable"))
+        .and(warp::body::json())
+        .and(routes::extract_auth_head())
+        .and_then(handler_disable)
+}
+pub async fn handler_disable(
+    code: TwoFactorCode,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_disable: {:?}", auth);
+    let code = extract_code(code)?;
+    let conn = db::PG_POOL.clone().get().unwrap();
+    if !db::two_factor::check(&conn, auth.id, &code) {
+        return Err(ApiError::forbidden("code", "is invalid"));
+    }
+    db::two_factor::disable(&conn, auth.id);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({ "twoFactorEnabled": false })),
+        StatusCode::OK,
+    ))
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_recovery_codes(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("api" / "user" / "2fa" / "recovery-codes"))
+        .and(warp::body::json())
+        .and(routes::extract_auth_head())
+        .and_then(handler_recovery_codes)
+}
+pub async fn handler_recovery_codes(
+    code: TwoFactorCode,
+    auth: Auth,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_recovery_codes: {:?}", auth);
+    let code = extract_code(code)?;
+    let conn = db::PG_POOL.clone().get().unwrap();
+    if !db::two_factor::check(&conn, auth.id, &code) {
+        return Err(ApiError::forbidden("code", "is invalid"));
+    }
+    let codes = db::two_factor::regenerate_recovery_codes(&conn, auth.id);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({ "recoveryCodes": codes })),
+        StatusCode::OK,
+    ))
+}
@@ -1,12 +1,16 @@
-use crate::auth::Auth;
-use crate::db::{self, users::UserCreationError};
-use crate::errors::{Errors, FieldValidator};
+use crate::auth::{self, Auth};
+use crate::config;
+use crate::db::audit::{self, Actor};
+use crate::db::{self, password_resets::ResetError, sessions::Device, users::UserCreationError};
+use crate::errors::{ApiError, FieldValidator};
+use crate::mail::{self, Mail};
+use crate::models::user::{User, UserAuth};
+use crate::password::validate_strength;
 use crate::routes;
-
-use std::convert::Infallible;
+use diesel::PgConnection;
 
 use warp::http::StatusCode;
-use warp::Filter;
+use warp::{Filter, Rejection, Reply};
 
 use serde::Deserialize;
 use serde_json::json;
@@ -23,7 +27,7 @@ struct NewUserData {
     username: Option<String>,
     #[validate(email)]
     email: Option<String>,
-    #[validate(length(min = 8))]
+    #[validate(custom = "validate_strength")]
     password: Option<String>,
 }
 
@@ -32,9 +36,13 @@ pub fn route_create_user(
     warp::path!("api" / "users")
         .and(warp::post())
         .and(warp::body::json())
+        .and(routes::extract_device())
         .and_then(handler_create_user)
 }
-pub async fn handler_create_user(new_user: NewUser) -> Result<impl warp::Reply, Infallible> {
+pub async fn handler_create_user(
+    new_user: NewUser,
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_create_user: {:?}", new_user);
 
     let new_user = new_user.user;
@@ -44,28 +52,23 @@ pub async fn handler_create_user(new_user: NewUser) -> Result<impl warp::Reply,
     let email = extractor.extract("email", new_user.email);
     let password = extractor.extract("password", new_user.password);
 
-    match extractor.check() {
-        Ok(_) => (),
-        Err(err) => return err.respond_to(),
-    };
+    extractor.check()?;
 
     let conn = db::PG_POOL.clone().get().unwrap();
     let result = db::users::create(&conn, &username, &email, &password);
     match result {
         Ok(user) => {
-            let tmpjson = json!({ "user": user.to_user_auth() });
-            Ok(warp::reply::with_status(
-                warp::reply::json(&tmpjson),
-                StatusCode::OK,
-            ))
+            send_verification(&user);
+            let (session, refresh_token) = db::sessions::create(&conn, user.id, &device);
+            let user_auth = user.to_user_auth(session.id, Some(refresh_token));
+            Ok(routes::user_auth_reply(&conn, user_auth, session.id))
         }
         Err(error) => {
             let field = match error {
                 UserCreationError::DuplicatedEmail => "email",
                 UserCreationError::DuplicatedUsername => "username",
             };
-            let err = Errors::new(&[(field, "has already been taken")]);
-            err.respond_to()
+            Err(ApiError::conflict(field, "has already been taken"))
         }
     }
 }
@@ -87,36 +90,175 @@ pub fn route_user_login() -> impl Filter<Extract = impl warp::Reply, Error = war
     warp::path!("api" / "users" / "login")
         .and(warp::post())
         .and(warp::body::json())
+        .and(routes::extract_device())
         .and_then(handler_user_login)
 }
-pub async fn handler_user_login(user: LoginUser) -> Result<impl warp::Reply, Infallible> {
+pub async fn handler_user_login(
+    user: LoginUser,
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
     let user = user.user;
     log::debug!("handler_user_login: {:?}", user);
 
     let mut extractor = FieldValidator::default();
     let email = extractor.extract("email", user.email);
     let password = extractor.extract("password", user.password);
-    match extractor.check() {
-        Ok(_) => (),
-        Err(err) => return err.respond_to(),
-    };
+    extractor.check()?;
 
     let conn = db::PG_POOL.clone().get().unwrap();
+    let account_key = db::login_failures::account_key(&email);
+    let address_key = device.ip.as_ref().map(db::login_failures::address_key);
+    let keys: Vec<String> = Some(account_key.clone())
+        .into_iter()
+        .chain(address_key.clone())
+        .collect();
+    if let Some(seconds) = db::login_failures::retry_after(&conn, &keys) {
+        return Err(ApiError::too_many_requests(
+            "login",
+            "too many failed attempts",
+            seconds,
+        ));
+    }
+
     let result = db::users::login(&conn, &email, &password);
-    match result {
-        Some(user) => {
-            // Ok(json!({ "user": user.to_user_auth() }))
-            let tmpjson = json!({ "user": user.to_user_auth() });
-            Ok(warp::reply::with_status(
-                warp::reply::json(&tmpjson),
-                StatusCode::OK,
-            ))
+    match &result {
+        Some(_) => {
+            db::login_failures::clear(&conn, &account_key);
         }
         None => {
-            let err = Errors::new(&[("email or password", "is invalid")]);
-            err.respond_to()
+            db::login_failures::record_failure(&conn, &account_key, db::CONFIG.login_max_failures);
+            if let Some(key) = &address_key {
+                db::login_failures::record_failure(
+                    &conn,
+                    key,
+                    db::CONFIG.login_max_failures_per_ip,
+                );
+            }
+            let actor = Actor {
+                ip: device.ip,
+                ..Actor::default()
+            };
+            db::audit::record(
+                &conn,
+                &actor,
+                audit::LOGIN_FAILED,
+                Some(&format!("email:{}", email)),
+                json!({ "factor": "password" }),
+            );
         }
     }
+    match result {
+        Some(user) => login_reply(&conn, user, &device),
+        None => Err(ApiError::bad_credentials("email or password")),
+    }
+}
+
+/// Open a session for a user who proved who they are, unless the second
+/// factor is still missing.
+pub fn login_reply(
+    conn: &PgConnection,
+    user: User,
+    device: &Device,
+) -> Result<warp::reply::Response, Rejection> {
+    match two_factor_challenge(conn, &user) {
+        Some(challenge) => {
+            let tmpjson = json!({ "twoFactorRequired": true, "challenge": challenge });
+            Ok(warp::reply::json(&tmpjson).into_response())
+        }
+        None => Ok(open_session(conn, &user, device)),
+    }
+}
+
+/// When the user has 2FA on, the challenge the code of the authenticator app has to follow.
+pub fn two_factor_challenge(conn: &PgConnection, user: &User) -> Option<String> {
+    if !db::two_factor::is_enabled(conn, user.id) {
+        return None;
+    }
+    Some(auth::purpose_token(
+        CHALLENGE_PURPOSE,
+        user.id,
+        &user.username,
+        config::CHALLENGE_EXPIRE_MINUTES,
+    ))
+}
+
+fn open_session(conn: &PgConnection, user: &User, device: &Device) -> warp::reply::Response {
+    let (user_auth, sid) = start_session(conn, user, device);
+    routes::user_auth_reply(conn, user_auth, sid)
+}
+
+/// Open a session for a finished login, return its tokens and id.
+pub fn start_session<'a>(
+    conn: &PgConnection,
+    user: &'a User,
+    device: &Device,
+) -> (UserAuth<'a>, i32) {
+    let (session, refresh_token) = db::sessions::create(conn, user.id, device);
+    db::audit::record(
+        conn,
+        &Actor::new(user.id, &user.username, device.ip),
+        audit::LOGIN,
+        Some(&format!("session:{}", session.id)),
+        json!({}),
+    );
+    (
+        user.to_user_auth(session.id, Some(refresh_token)),
+        session.id,
+    )
+}
+
+// --------------------------------------------------------------------------------------------------------
+const CHALLENGE_PURPOSE: &str = "2fa";
+
+#[derive(Deserialize, Debug)]
+pub struct LoginChallenge {
+    challenge: Option<String>,
+    code: Option<String>,
+}
+
+pub fn route_user_login_2fa(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "users" / "login" / "2fa")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(routes::extract_device())
+        .and_then(handler_user_login_2fa)
+}
+pub async fn handler_user_login_2fa(
+    login: LoginChallenge,
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_user_login_2fa");
+
+    let mut extractor = FieldValidator::default();
+    let challenge = extractor.extract("challenge", login.challenge);
+    let code = extractor.extract("code", login.code);
+    extractor.check()?;
+
+    let user_id = match auth::decode_purpose_token(&challenge, CHALLENGE_PURPOSE) {
+        Some((id, _)) => id,
+        None => return Err(ApiError::bad_credentials("challenge")),
+    };
+    let conn = db::PG_POOL.clone().get().unwrap();
+    if !db::two_factor::check(&conn, user_id, &code) {
+        let actor = Actor {
+            id: Some(user_id),
+            ip: device.ip,
+            ..Actor::default()
+        };
+        db::audit::record(
+            &conn,
+            &actor,
+            audit::LOGIN_FAILED,
+            Some(&format!("user:{}", user_id)),
+            json!({ "factor": "2fa" }),
+        );
+        return Err(ApiError::bad_credentials("code"));
+    }
+    match db::users::find(&conn, user_id) {
+        Some(user) => Ok(open_session(&conn, &user, &device)),
+        None => Err(ApiError::bad_credentials("challenge")),
+    }
 }
 
 // --------------------------------------------------------------------------------------------------------
@@ -127,22 +269,16 @@ pub fn route_get_user() -> impl Filter<Extract = impl warp::Reply, Error = warp:
         .and(routes::extract_auth_head())
         .and_then(handler_get_user)
 }
-pub async fn handler_get_user(auth: Auth) -> Result<impl warp::Reply, Infallible> {
+pub async fn handler_get_user(auth: Auth) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_get_user: {:?}", auth);
     let conn = db::PG_POOL.clone().get().unwrap();
     let result = db::users::find(&conn, auth.id);
     match result {
         Some(user) => {
-            let tmpjson = json!({ "user": user.to_user_auth() });
-            Ok(warp::reply::with_status(
-                warp::reply::json(&tmpjson),
-                StatusCode::OK,
-            ))
-        }
-        None => {
-            let err = Errors::new(&[("get user ", "some error")]);
-            err.respond_to()
+            let user_auth = user.to_user_auth(auth.sid, None);
+            Ok(routes::user_auth_reply(&conn, user_auth, auth.sid))
         }
+        None => Err(ApiError::not_found("user")),
     }
 }
 // --------------------------------------------------------------------------------------------------------
@@ -157,26 +293,308 @@ pub fn route_put_user() -> impl Filter<Extract = impl warp::Reply, Error = warp:
         .and(warp::put())
         .and(warp::body::json())
         .and(routes::extract_auth_head())
+        .and(routes::extract_device())
         .and_then(handler_put_user)
 }
 pub async fn handler_put_user(
     user: UpdateUser,
     auth: Auth,
-) -> Result<impl warp::Reply, Infallible> {
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
     log::debug!("handler_put_user: {:?} \n {:?}", user, auth);
+    // a new password has to be as strong as at sign up
+    FieldValidator::validate(&user.user).check()?;
     let conn = db::PG_POOL.clone().get().unwrap();
+    let before = db::users::find(&conn, auth.id);
     let result = db::users::update(&conn, auth.id, &user.user);
     match result {
-        Some(user) => {
-            let tmpjson = json!({ "user": user.to_user_auth() });
+        Some(updated) => {
+            if user.user.changes_email() && updated.verified_at.is_none() {
+                send_verification(&updated);
+            }
+            let actor = Actor::from_auth(&auth, device.ip);
+            let target = format!("user:{}", updated.id);
+            let changes = audit::diff(
+                &serde_json::to_value(&before).unwrap_or_default(),
+                &serde_json::to_value(&updated).unwrap_or_default(),
+            );
+            if changes.as_object().is_some_and(|c| !c.is_empty()) {
+                db::audit::record(&conn, &actor, audit::USER_UPDATE, Some(&target), changes);
+            }
+            if user.user.changes_password() {
+                db::audit::record(
+                    &conn,
+                    &actor,
+                    audit::PASSWORD_CHANGE,
+                    Some(&target),
+                    json!({}),
+                );
+            }
+            // changing the password revoked every session, this one included
+            if user.user.changes_password() {
+                let (session, refresh_token) = db::sessions::create(&conn, updated.id, &device);
+                let user_auth = updated.to_user_auth(session.id, Some(refresh_token));
+                Ok(routes::user_auth_reply(&conn, user_auth, session.id))
+            } else {
+                let user_auth = updated.to_user_auth(auth.sid, None);
+                Ok(routes::user_auth_reply(&conn, user_auth, auth.sid))
+            }
+        }
+        None => Err(ApiError::internal()),
+    }
+}
+
+// --------------------------------------------------------------------------------------------------------
+pub fn route_user_logout(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "users" / "logout")
+        .and(warp::post())
+        .and(routes::request_token())
+        .and_then(handler_user_logout)
+}
+pub async fn handler_user_logout(token: Option<String>) -> Result<impl warp::Reply, Rejection> {
+    // an expired access token may still close its own session
+    let auth = token
+        .as_ref()
+        .and_then(|token| auth::decode_token(token))
+        .ok_or_else(ApiError::unauthorized)?;
+    log::debug!("handler_user_logout: {:?}", auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    db::sessions::revoke(&conn, auth.sid, auth.id);
+    Ok(routes::clear_session_cookies(StatusCode::OK))
+}
+
+// --------------------------------------------------------------------------------------------------------
+#[derive(Deserialize, Debug)]
+pub struct RefreshToken {
+    #[serde(rename = "refreshToken")]
+    refresh_token: Option<String>,
+}
+
+pub fn route_refresh_token(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "users" / "token" / "refresh")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(warp::cookie::optional::<String>(config::REFRESH_COOKIE))
+        .and_then(handler_refresh_token)
+}
+pub async fn handler_refresh_token(
+    refresh: RefreshToken,
+    cookie: Option<String>,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_refresh_token");
+
+    // the front-end of `COOKIE_SESSIONS` can't read the token to send it
+    let cookie = cookie.filter(|_| db::CONFIG.cookie_sessions);
+    let mut extractor = FieldValidator::default();
+    let refresh_token = extractor.extract("refreshToken", refresh.refresh_token.or(cookie));
+    extractor.check()?;
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let result = db::sessions::refresh(&conn, &refresh_token).and_then(|(session, token)| {
+        db::users::find(&conn, session.user).map(|u| (u, session, token))
+    });
+    match result {
+        Some((user, session, token)) => {
+            let user_auth = user.to_user_auth(session.id, Some(token));
+            Ok(routes::user_auth_reply(&conn, user_auth, session.id))
+        }
+        None => Err(ApiError::bad_credentials("refreshToken")),
+    }
+}
+
+// --------------------------------------------------------------------------------------------------------
+const VERIFY_PURPOSE: &str = "verify";
+
+fn send_verification(user: &User) {
+    let token = auth::purpose_token(
+        VERIFY_PURPOSE,
+        user.id,
+        &user.email,
+        config::VERIFY_EXPIRE_MINUTES,
+    );
+    mail::send(Mail {
+        to: user.email.clone(),
+        subject: format!("Verify your email for {}", db::CONFIG.server_name),
+        body: format!(
+            "Hi {},\n\nplease open the link below to verify your email address:\n\n{}/#/verify?token={}\n",
+            user.username,
+            db::CONFIG.public_url,
+            token
+        ),
+    });
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VerifyEmail {
+    token: Option<String>,
+}
+
+pub fn route_verify_email(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "users" / "verify")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then(handler_verify_email)
+}
+pub async fn handler_verify_email(verify: VerifyEmail) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_verify_email");
+
+    let mut extractor = FieldValidator::default();
+    let token = extractor.extract("token", verify.token);
+    extractor.check()?;
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let verified = auth::decode_purpose_token(&token, VERIFY_PURPOSE)
+        .map(|(id, email)| db::users::verify_email(&conn, id, &email))
+        .unwrap_or(false);
+    if verified {
+        Ok(warp::reply::with_status(
+            warp::reply::json(&json!({ "verified": true })),
+            StatusCode::OK,
+        ))
+    } else {
+        Err(ApiError::validation("token", "is invalid"))
+    }
+}
+
+pub fn route_resend_verification(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "users" / "verify" / "resend")
+        .and(warp::post())
+        .and(routes::extract_auth_head())
+        .and_then(handler_resend_verification)
+}
+pub async fn handler_resend_verification(auth: Auth) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_resend_verification: {:?}", auth);
+    let conn = db::PG_POOL.clone().get().unwrap();
+    match db::users::find(&conn, auth.id) {
+        Some(user) if user.verified_at.is_none() => {
+            send_verification(&user);
+            Ok(StatusCode::OK)
+        }
+        Some(_) => Err(ApiError::conflict("email", "is already verified")),
+        None => Err(ApiError::not_found("user")),
+    }
+}
+
+// --------------------------------------------------------------------------------------------------------
+#[derive(Deserialize, Debug)]
+pub struct ForgotPassword {
+    email: Option<String>,
+}
+
+pub fn route_forgot_password(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "users" / "password" / "forgot")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then(handler_forgot_password)
+}
+pub async fn handler_forgot_password(
+    forgot: ForgotPassword,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_forgot_password: {:?}", forgot);
+
+    let mut extractor = FieldValidator::default();
+    let email = extractor.extract("email", forgot.email);
+    extractor.check()?;
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    if let Some((user, token)) = db::password_resets::request(&conn, &email) {
+        let reset = Mail {
+            to: user.email,
+            subject: format!("Reset your password for {}", db::CONFIG.server_name),
+            body: format!(
+                "Hi {},\n\nopen the link below within {} minutes to choose a new password:\n\n{}/#/reset-password?token={}\n\nIf you didn't ask for it, just ignore this mail.\n",
+                user.username,
+                config::RESET_EXPIRE_MINUTES,
+                db::CONFIG.public_url,
+                token
+            ),
+        };
+        // send doesn't wait for the delivery, its time can't tell that the account exists
+        mail::send(reset);
+    }
+    let tmpjson = json!({ "status": "If the email is registered, a reset link has been sent." });
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tmpjson),
+        StatusCode::OK,
+    ))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ResetPassword {
+    reset: ResetPasswordData,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+struct ResetPasswordData {
+    token: Option<String>,
+    #[validate(custom = "validate_strength")]
+    password: Option<String>,
+}
+
+pub fn route_reset_password(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "users" / "password" / "reset")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(routes::extract_device())
+        .and_then(handler_reset_password)
+}
+pub async fn handler_reset_password(
+    reset: ResetPassword,
+    device: Device,
+) -> Result<impl warp::Reply, Rejection> {
+    log::debug!("handler_reset_password");
+    let reset = reset.reset;
+
+    let mut extractor = FieldValidator::validate(&reset);
+    let token = extractor.extract("token", reset.token);
+    let password = extractor.extract("password", reset.password);
+    extractor.check()?;
+
+    let conn = db::PG_POOL.clone().get().unwrap();
+    let address_key = device.ip.as_ref().map(db::login_failures::address_key);
+    if let Some(key) = &address_key {
+        if let Some(seconds) = db::login_failures::retry_after(&conn, std::slice::from_ref(key)) {
+            return Err(ApiError::too_many_requests(
+                "reset",
+                "too many failed attempts",
+                seconds,
+            ));
+        }
+    }
+    match db::password_resets::reset(&conn, &token, &password) {
+        Ok(user) => {
+            db::audit::record(
+                &conn,
+                &Actor::new(user.id, &user.username, device.ip),
+                audit::PASSWORD_RESET,
+                Some(&format!("user:{}", user.id)),
+                json!({}),
+            );
             Ok(warp::reply::with_status(
-                warp::reply::json(&tmpjson),
+                warp::reply::json(&json!({ "reset": true })),
                 StatusCode::OK,
             ))
         }
-        None => {
-            let err = Errors::new(&[("put user ", "some error")]);
-            err.respond_to()
+        Err(ResetError::Locked(seconds)) => Err(ApiError::too_many_requests(
+            "reset",
+            "too many failed attempts",
+            seconds,
+        )),
+        Err(ResetError::Invalid) => {
+            if let Some(key) = &address_key {
+                db::login_failures::record_failure(
+                    &conn,
+                    key,
+                    db::CONFIG.login_max_failures_per_ip,
+                );
+            }
+            Err(ApiError::validation("token", "is invalid"))
         }
     }
 }
@@ -1,3 +1,72 @@
+table! {
+    access_tokens (id) {
+        id -> Int4,
+        user -> Int4,
+        name -> Text,
+        token_hash -> Text,
+        scopes -> Array<Text>,
+        created_at -> Timestamptz,
+        expires_at -> Nullable<Timestamptz>,
+        last_used_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    article_authors (article, user) {
+        article -> Int4,
+        user -> Int4,
+        created_at -> Timestamptz,
+        accepted_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    article_reactions (article, user, emoji) {
+        article -> Int4,
+        user -> Int4,
+        emoji -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    article_revisions (id) {
+        id -> Int4,
+        article -> Int4,
+        number -> Int4,
+        editor -> Nullable<Int4>,
+        title -> Text,
+        description -> Text,
+        body -> Text,
+        tag_list -> Array<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    article_slugs (slug) {
+        slug -> Text,
+        article -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    article_tags (article, tag) {
+        article -> Int4,
+        tag -> Int4,
+    }
+}
+
+table! {
+    article_views (article, day) {
+        article -> Int4,
+        day -> Date,
+        views -> Int4,
+    }
+}
+
 table! {
     articles (id) {
         id -> Int4,
@@ -10,6 +79,42 @@ table! {
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
         favorites_count -> Int4,
+        status -> Text,
+        published_at -> Nullable<Timestamptz>,
+        body_html -> Nullable<Text>,
+        views_count -> Int4,
+    }
+}
+
+table! {
+    audit_events (id) {
+        id -> Int4,
+        actor -> Nullable<Int4>,
+        actor_name -> Nullable<Text>,
+        action -> Text,
+        target -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        diff -> Jsonb,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    bookmarks (user, article) {
+        user -> Int4,
+        article -> Int4,
+        folder -> Nullable<Text>,
+        note -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    comment_reactions (comment, user, emoji) {
+        comment -> Int4,
+        user -> Int4,
+        emoji -> Text,
+        created_at -> Timestamptz,
     }
 }
 
@@ -21,6 +126,7 @@ table! {
         author -> Int4,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        body_html -> Nullable<Text>,
     }
 }
 
@@ -28,6 +134,7 @@ table! {
     favorites (user, article) {
         user -> Int4,
         article -> Int4,
+        created_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -38,6 +145,92 @@ table! {
     }
 }
 
+table! {
+    login_failures (key) {
+        key -> Text,
+        failures -> Int4,
+        last_failed_at -> Timestamptz,
+        locked_until -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    outbox (id) {
+        id -> Int4,
+        recipient -> Text,
+        subject -> Text,
+        body -> Text,
+        created_at -> Timestamptz,
+        sent_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    password_resets (id) {
+        id -> Int4,
+        user -> Int4,
+        token_hash -> Text,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    recovery_codes (id) {
+        id -> Int4,
+        user -> Int4,
+        code_hash -> Text,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Int4,
+        user -> Int4,
+        refresh_hash -> Text,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+        user_agent -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        last_seen_at -> Timestamptz,
+        previous_refresh_hash -> Nullable<Text>,
+        csrf_token -> Nullable<Text>,
+    }
+}
+
+table! {
+    tags (id) {
+        id -> Int4,
+        name -> Text,
+        blocked -> Bool,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    totp_credentials (user) {
+        user -> Int4,
+        secret -> Text,
+        enabled_at -> Nullable<Timestamptz>,
+        last_step -> Nullable<Int8>,
+        failures -> Int4,
+        failed_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    user_identities (id) {
+        id -> Int4,
+        user -> Int4,
+        issuer -> Text,
+        subject -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     users (id) {
         id -> Int4,
@@ -46,13 +239,59 @@ table! {
         bio -> Nullable<Text>,
         image -> Nullable<Text>,
         hash -> Text,
+        role -> Text,
+        verified_at -> Nullable<Timestamptz>,
     }
 }
 
+joinable!(access_tokens -> users (user));
+joinable!(article_authors -> articles (article));
+joinable!(article_authors -> users (user));
+joinable!(article_reactions -> articles (article));
+joinable!(article_reactions -> users (user));
+joinable!(article_revisions -> articles (article));
+joinable!(article_revisions -> users (editor));
+joinable!(article_slugs -> articles (article));
+joinable!(article_tags -> articles (article));
+joinable!(article_tags -> tags (tag));
+joinable!(article_views -> articles (article));
 joinable!(articles -> users (author));
+joinable!(bookmarks -> articles (article));
+joinable!(bookmarks -> users (user));
+joinable!(comment_reactions -> comments (comment));
+joinable!(comment_reactions -> users (user));
 joinable!(comments -> articles (article));
 joinable!(comments -> users (author));
 joinable!(favorites -> articles (article));
 joinable!(favorites -> users (user));
+joinable!(password_resets -> users (user));
+joinable!(recovery_codes -> users (user));
+joinable!(sessions -> users (user));
+joinable!(totp_credentials -> users (user));
+joinable!(user_identities -> users (user));
 
-allow_tables_to_appear_in_same_query!(articles, comments, favorites, follows, users,);
+allow_tables_to_appear_in_same_query!(
+    access_tokens,
+    article_authors,
+    article_reactions,
+    article_revisions,
+    article_slugs,
+    article_tags,
+    article_views,
+    articles,
+    audit_events,
+    bookmarks,
+    comment_reactions,
+    comments,
+    favorites,
+    follows,
+    login_failures,
+    outbox,
+    password_resets,
+    recovery_codes,
+    sessions,
+    tags,
+    totp_credentials,
+    user_identities,
+    users,
+);